@@ -11,6 +11,8 @@ fn main() -> Result<()> {
         (@arg OUTPUT: <output> "Output JPEG file")
         (@arg QUALITY: -q --quality <quality> 
             "Quality of the output JPEG file (1 is worst, 100 is best)")
+        (@arg SUBSAMP: -s --subsamp <subsamp>
+            "Chrominance subsampling (4:4:4, 4:2:2, 4:2:0, 4:4:0, 4:1:1 or gray)")
     ).get_matches();
 
     let image = image::io::Reader::open(args.value_of("INPUT").unwrap())?
@@ -31,6 +33,11 @@ fn main() -> Result<()> {
         compressor.set_quality(quality);
     }
 
+    if let Some(subsamp) = args.value_of("SUBSAMP") {
+        let subsamp = subsamp.parse().context("could not parse value of --subsamp")?;
+        compressor.set_subsamp(subsamp);
+    }
+
     let image_jpeg = compressor.compress_to_owned(Image {
         pixels: image_flat.as_slice(),
         width: extents.1,
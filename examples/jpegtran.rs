@@ -13,7 +13,7 @@ fn main() -> Result<()> {
         (@arg FLIP: --flip [direction]
             "Performs a flip ('horizontal' or 'vertical')")
         (@arg ROTATE: --rotate [angle]
-            "Rotates the image (angle is 90, 180 or 270)")
+            "Rotates the image clockwise (angle is a multiple of 90)")
         (@arg TRANSPOSE: --transpose ...
             "Transpose image (flip along upper left to lower right axis)")
         (@arg TRANSVERSE: --transverse ...
@@ -41,15 +41,8 @@ fn main() -> Result<()> {
             bail!("unknown value of --flip")
         }
     } else if let Some(angle) = args.value_of("ROTATE") {
-        if angle == "90" {
-            transform.op = TransformOp::Rot90;
-        } else if angle == "180" {
-            transform.op = TransformOp::Rot180;
-        } else if angle == "270" {
-            transform.op = TransformOp::Rot270;
-        } else {
-            bail!("unknown value of --rotate")
-        }
+        let angle = angle.parse().context("could not parse value of --rotate")?;
+        transform.op = TransformOp::rotate(angle).context("unsupported value of --rotate")?;
     } else if args.is_present("TRANSPOSE") {
         transform.op = TransformOp::Transpose;
     } else if args.is_present("TRANSVERSE") {
@@ -1,7 +1,8 @@
-use std::{ptr, slice};
-use std::convert::{AsRef, AsMut};
+use std::{io, ptr, slice};
+use std::convert::{AsRef, AsMut, TryInto as _};
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
+use crate::common::{Error, Result};
 
 /// Owned buffer with JPEG data.
 ///
@@ -14,6 +15,10 @@ pub struct OwnedBuf {
     len: usize,
 }
 
+// the memory is owned exclusively by the buffer and `tjFree()` may be called from any thread
+unsafe impl Send for OwnedBuf {}
+unsafe impl Sync for OwnedBuf {}
+
 impl Deref for OwnedBuf {
     type Target = [u8];
     fn deref(&self) -> &[u8] { unsafe { deref(self.ptr, self.len) } }
@@ -38,11 +43,47 @@ impl OwnedBuf {
     ///
     /// Panics if `len` overflows or if the memory cannot be allocated.
     pub fn allocate(len: usize) -> OwnedBuf {
-        let ptr = unsafe { raw::tjAlloc(len as libc::c_int) };
+        let c_len: libc::c_int = len.try_into().expect("OwnedBuf length overflows");
+        let ptr = unsafe { raw::tjAlloc(c_len) };
         assert!(!ptr.is_null(), "tjAlloc() returned null");
         OwnedBuf { ptr, len }
     }
 
+    /// Allocates a buffer with given length filled with zeros.
+    ///
+    /// Panics if `len` overflows or if the memory cannot be allocated.
+    pub(crate) fn zeroed(len: usize) -> OwnedBuf {
+        if len == 0 {
+            return OwnedBuf::new()
+        }
+        let buf = Self::allocate(len);
+        unsafe { ptr::write_bytes(buf.ptr, 0, len) }
+        buf
+    }
+
+    /// Allocates a buffer with given length filled with zeros, or returns an error if `len` does
+    /// not fit into the `int` that `tjAlloc()` takes or if the memory cannot be allocated.
+    pub(crate) fn try_zeroed(len: usize) -> Result<OwnedBuf> {
+        let buf = Self::try_allocate(len)?;
+        unsafe { ptr::write_bytes(buf.ptr, 0, len) }
+        Ok(buf)
+    }
+
+    /// Allocates a buffer with given length, or returns an error if `len` does not fit into the
+    /// `int` that `tjAlloc()` takes or if the memory cannot be allocated.
+    pub(crate) fn try_allocate(len: usize) -> Result<OwnedBuf> {
+        if len == 0 {
+            return Ok(OwnedBuf::new())
+        }
+        let c_len: libc::c_int = len.try_into()
+            .map_err(|_| Error::IntegerOverflow("OwnedBuf length"))?;
+        let ptr = unsafe { raw::tjAlloc(c_len) };
+        if ptr.is_null() {
+            return Err(Error::Null())
+        }
+        Ok(OwnedBuf { ptr, len })
+    }
+
     /// Creates a new buffer copied from a slice.
     pub fn copy_from_slice(data: &[u8]) -> OwnedBuf {
         let buf = Self::allocate(data.len());
@@ -54,6 +95,12 @@ impl OwnedBuf {
     pub fn len(&self) -> usize {
         self.len
     }
+
+    /// Takes ownership of `len` bytes at `ptr`, which must have been allocated by TurboJPEG (or be
+    /// null with zero `len`).
+    pub(crate) unsafe fn from_raw(ptr: *mut u8, len: usize) -> OwnedBuf {
+        OwnedBuf { ptr, len }
+    }
 }
 
 impl Drop for OwnedBuf {
@@ -83,7 +130,13 @@ impl Drop for OwnedBuf {
 pub struct OutputBuf<'a> {
     pub(crate) ptr: *mut u8,
     pub(crate) len: usize,
+    /// Size of the allocated memory (for owned buffers) or of the borrowed slice.
+    pub(crate) cap: usize,
+    /// False if TurboJPEG reallocated the buffer, in which case the allocated memory may be larger
+    /// than `cap`.
+    pub(crate) cap_exact: bool,
     pub(crate) is_owned: bool,
+    pub(crate) policy: GrowthPolicy,
     pub(crate) _phantom: PhantomData<&'a mut [u8]>,
 }
 
@@ -108,7 +161,10 @@ impl<'a> OutputBuf<'a> {
         OutputBuf {
             ptr: slice.as_mut_ptr(),
             len: slice.len(),
+            cap: slice.len(),
+            cap_exact: true,
             is_owned: false,
+            policy: GrowthPolicy::default(),
             _phantom: PhantomData,
         }
     }
@@ -120,7 +176,10 @@ impl<'a> OutputBuf<'a> {
         OutputBuf {
             ptr,
             len,
+            cap: len,
+            cap_exact: true,
             is_owned: true,
+            policy: GrowthPolicy::default(),
             _phantom: PhantomData,
         }
     }
@@ -140,6 +199,160 @@ impl<'a> OutputBuf<'a> {
         self.len
     }
 
+    /// Returns the capacity of the buffer.
+    ///
+    /// For owned buffers, this is the size of the memory that the buffer holds on to, which may be
+    /// larger than [`len()`](OutputBuf::len). For borrowed buffers, this is the length of the
+    /// borrowed slice.
+    ///
+    /// When TurboJPEG grows an owned buffer, it does not tell us the size of the new allocation, so
+    /// the capacity is then only known to be at least the length of the data. With the
+    /// [`Exact`][GrowthPolicy::Exact] and [`Capped`][GrowthPolicy::Capped] policies, such a buffer
+    /// is always reallocated to the length of the data, so the capacity is exact again.
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    /// Set the policy that controls how much memory an owned buffer retains between operations.
+    ///
+    /// The policy is applied after every compression or transformation into this buffer, and it
+    /// is ignored for borrowed buffers. The default is [`GrowthPolicy::Doubling`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// let mut output_buf = turbojpeg::OutputBuf::new_owned();
+    /// output_buf.set_growth_policy(turbojpeg::GrowthPolicy::Capped(64 * 1024));
+    ///
+    /// // compress one large frame...
+    /// let large = turbojpeg::Image::mandelbrot(2000, 2000, turbojpeg::PixelFormat::RGB);
+    /// compressor.compress(large.as_deref(), &mut output_buf)?;
+    /// assert!(output_buf.len() > 64 * 1024);
+    ///
+    /// // ...followed by a small one, after which the buffer does not keep the large allocation
+    /// let small = turbojpeg::Image::mandelbrot(100, 100, turbojpeg::PixelFormat::RGB);
+    /// compressor.compress(small.as_deref(), &mut output_buf)?;
+    /// assert!(output_buf.capacity() <= 64 * 1024);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_growth_policy(&mut self, policy: GrowthPolicy) {
+        self.policy = policy;
+    }
+
+    /// Returns the policy set by [`set_growth_policy()`](OutputBuf::set_growth_policy).
+    pub fn growth_policy(&self) -> GrowthPolicy {
+        self.policy
+    }
+
+    /// Limit the memory that an owned buffer retains between operations to `max_capacity` bytes.
+    ///
+    /// This is a shorthand for setting the [`GrowthPolicy::Capped`] policy.
+    pub fn set_max_capacity(&mut self, max_capacity: usize) {
+        self.set_growth_policy(GrowthPolicy::Capped(max_capacity));
+    }
+
+    /// Shrink the capacity of an owned buffer to `min_capacity`, or to its length if that is
+    /// larger.
+    ///
+    /// This reallocates the buffer and copies the data if the capacity is larger than needed. It
+    /// does nothing for borrowed buffers.
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        let new_cap = usize::max(self.len, min_capacity);
+        if !self.is_owned || (self.cap_exact && self.cap <= new_cap) {
+            return
+        }
+
+        let mut new_buf = OwnedBuf::new();
+        if new_cap != 0 {
+            new_buf = OwnedBuf::allocate(new_cap);
+            new_buf[..self.len].copy_from_slice(&self[..]);
+        }
+        unsafe { raw::tjFree(self.ptr) };
+        self.ptr = new_buf.ptr;
+        self.cap = new_cap;
+        self.cap_exact = true;
+        new_buf.ptr = ptr::null_mut(); // do not free the pointer in the OwnedBuf destructor
+    }
+
+    /// Updates the capacity after TurboJPEG wrote into the buffer, which was at `ptr_before`.
+    pub(crate) fn update_cap(&mut self, ptr_before: *mut u8) {
+        if self.is_owned && self.ptr != ptr_before {
+            // TurboJPEG reallocated the buffer, we only know that the new buffer can hold the data
+            self.cap = if self.ptr.is_null() { 0 } else { self.len };
+            self.cap_exact = self.ptr.is_null();
+        }
+    }
+
+    /// Shrinks the buffer after a successful operation according to the growth policy.
+    pub(crate) fn apply_growth_policy(&mut self) {
+        if !self.is_owned {
+            return
+        }
+        match self.policy {
+            GrowthPolicy::Doubling => {},
+            GrowthPolicy::Exact => self.shrink_to(0),
+            // the real capacity of a buffer reallocated by TurboJPEG may exceed `max_cap`
+            GrowthPolicy::Capped(_) if !self.cap_exact => self.shrink_to(0),
+            GrowthPolicy::Capped(max_cap) => if self.cap > max_cap { self.shrink_to(max_cap) },
+        }
+    }
+
+    /// Ensures that an owned buffer can hold `additional` more bytes, growing it geometrically.
+    ///
+    /// Returns an error if the new length overflows or if the memory cannot be allocated. Panics
+    /// if the buffer is borrowed.
+    fn reserve(&mut self, additional: usize) -> Result<()> {
+        assert!(self.is_owned);
+        let new_len = self.len.checked_add(additional).ok_or(Error::IntegerOverflow("buf len"))?;
+        if new_len <= self.cap {
+            return Ok(())
+        }
+
+        // TurboJPEG cannot allocate more than `c_int::MAX` bytes, so don't double past that
+        let max_cap = libc::c_int::MAX as usize;
+        let new_cap = usize::max(new_len, self.cap.saturating_mul(2).min(max_cap));
+        let mut new_buf = OwnedBuf::try_allocate(new_cap)?;
+        new_buf[..self.len].copy_from_slice(&self[..]);
+        unsafe { raw::tjFree(self.ptr) };
+        self.ptr = new_buf.ptr;
+        self.cap = new_cap;
+        self.cap_exact = true;
+        new_buf.ptr = ptr::null_mut(); // do not free the pointer in the OwnedBuf destructor
+        Ok(())
+    }
+
+    /// Inserts `data` at `offset`, moving the rest of the buffer after it.
+    ///
+    /// Owned buffers are reallocated, borrowed buffers return an error if the data does not fit
+    /// into the slice. Returns an error if the new length overflows or if the memory cannot be
+    /// allocated.
+    pub(crate) fn insert(&mut self, offset: usize, data: &[u8]) -> Result<()> {
+        assert!(offset <= self.len);
+        let new_len = self.len.checked_add(data.len()).ok_or(Error::IntegerOverflow("buf len"))?;
+        if self.is_owned {
+            let mut new_buf = OwnedBuf::try_allocate(new_len)?;
+            new_buf[..offset].copy_from_slice(&self[..offset]);
+            new_buf[offset..][..data.len()].copy_from_slice(data);
+            new_buf[offset + data.len()..].copy_from_slice(&self[offset..]);
+            unsafe { raw::tjFree(self.ptr) };
+            self.ptr = new_buf.ptr;
+            self.cap = new_len;
+            self.cap_exact = true;
+            new_buf.ptr = ptr::null_mut(); // do not free the pointer in the OwnedBuf destructor
+        } else {
+            if new_len > self.cap {
+                return Err(Error::OutputTooSmall(new_len))
+            }
+            unsafe {
+                ptr::copy(self.ptr.add(offset), self.ptr.add(offset + data.len()), self.len - offset);
+                ptr::copy_nonoverlapping(data.as_ptr(), self.ptr.add(offset), data.len());
+            }
+        }
+        self.len = new_len;
+        Ok(())
+    }
+
     /// Converts this buffer into an owned buffer.
     ///
     /// If `self` is owned, this is a trivial operation, otherwise we must copy the data from the
@@ -157,6 +370,102 @@ impl<'a> OutputBuf<'a> {
     }
 }
 
+/// Policy that controls how much memory an owned [`OutputBuf`] retains between operations.
+///
+/// TurboJPEG grows an owned buffer as needed while it writes the compressed data (by doubling its
+/// size). The policy decides what happens with the extra memory when the operation is done, which
+/// matters when you reuse a buffer for many images of different sizes. Use
+/// [`OutputBuf::set_growth_policy()`] to set the policy.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum GrowthPolicy {
+    /// Shrink the buffer to the exact length of the data after every operation.
+    ///
+    /// This uses the least memory, but the buffer will usually be reallocated in every operation.
+    Exact,
+
+    /// Keep all memory that the buffer has grown to (this is the default).
+    ///
+    /// This avoids reallocations when the buffer is reused, but a single large image makes the
+    /// buffer hold on to a large allocation for the rest of its life.
+    #[default]
+    Doubling,
+
+    /// Keep at most the given number of bytes.
+    ///
+    /// If the capacity exceeds this limit after an operation, the buffer is shrunk to the limit,
+    /// or to the length of the data if it is larger. If TurboJPEG grew the buffer during the
+    /// operation, the size of the new allocation is not known, so the buffer is shrunk to the
+    /// length of the data.
+    Capped(usize),
+}
+
+/// Appends bytes after the data in the buffer.
+///
+/// Owned buffers grow as needed. Borrowed buffers cannot grow, so only the bytes that fit into the
+/// rest of the borrowed slice are written (and [`write_all()`](io::Write::write_all) returns an
+/// error if the data does not fit). If an owned buffer cannot grow, because its length would
+/// overflow or the memory cannot be allocated, [`write()`](io::Write::write) returns an error of
+/// kind [`io::ErrorKind::InvalidInput`] or [`io::ErrorKind::OutOfMemory`].
+///
+/// Note that a buffer fresh from [`OutputBuf::borrowed()`] has the length of the whole slice, so
+/// there is no room after its data: [`write()`](io::Write::write) returns `Ok(0)` and
+/// [`write_all()`](io::Write::write_all) fails with [`io::ErrorKind::WriteZero`]. Writing into a
+/// borrowed buffer is only useful after a compression or transformation into it, which sets the
+/// length to the size of the JPEG data.
+///
+/// # Example
+///
+/// ```
+/// use std::io::Write as _;
+///
+/// // compress an image into an owned buffer
+/// let image = turbojpeg::Image::mandelbrot(100, 100, turbojpeg::PixelFormat::RGB);
+/// let mut compressor = turbojpeg::Compressor::new()?;
+/// let mut output_buf = turbojpeg::OutputBuf::new_owned();
+/// compressor.compress(image.as_deref(), &mut output_buf)?;
+/// let jpeg_len = output_buf.len();
+///
+/// // append a custom trailer after the JPEG data
+/// write!(output_buf, "trailer {}", jpeg_len)?;
+/// assert_eq!(output_buf.len(), jpeg_len + format!("trailer {}", jpeg_len).len());
+/// assert!(output_buf.ends_with(format!("trailer {}", jpeg_len).as_bytes()));
+///
+/// // the trailer is ignored when decompressing
+/// let decompressed = turbojpeg::decompress(&output_buf, turbojpeg::PixelFormat::RGB)?;
+/// assert_eq!((decompressed.width, decompressed.height), (100, 100));
+///
+/// // a fresh borrowed buffer is full, so nothing can be written into it
+/// let mut slice = [0; 16];
+/// let mut borrowed_buf = turbojpeg::OutputBuf::borrowed(&mut slice);
+/// assert_eq!(borrowed_buf.write(b"trailer")?, 0);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+impl<'a> io::Write for OutputBuf<'a> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let len = if self.is_owned {
+            self.reserve(data.len()).map_err(|err| {
+                let kind = match err {
+                    Error::Null() => io::ErrorKind::OutOfMemory,
+                    _ => io::ErrorKind::InvalidInput,
+                };
+                io::Error::new(kind, err)
+            })?;
+            data.len()
+        } else {
+            usize::min(data.len(), self.cap - self.len)
+        };
+        if len != 0 {
+            unsafe { ptr::copy_nonoverlapping(data.as_ptr(), self.ptr.add(self.len), len) };
+            self.len += len;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 impl<'a> Drop for OutputBuf<'a> {
     fn drop(&mut self) {
         if self.is_owned {
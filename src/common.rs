@@ -119,6 +119,65 @@ impl PixelFormat {
             PixelFormat::CMYK => 4,
         }
     }
+
+    /// The meaning of the bytes in a pixel, in the order in which they are stored in memory.
+    ///
+    /// The length of the returned slice is always equal to [`size()`](PixelFormat::size).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use turbojpeg::{Channel, PixelFormat};
+    /// assert_eq!(PixelFormat::RGB.channel_order(), &[Channel::R, Channel::G, Channel::B]);
+    /// assert_eq!(PixelFormat::GRAY.channel_order(), &[Channel::Gray]);
+    ///
+    /// // find the offset of the green component in a BGRA pixel
+    /// let format = PixelFormat::BGRA;
+    /// let green = format.channel_order().iter().position(|&c| c == Channel::G);
+    /// assert_eq!(green, Some(1));
+    /// ```
+    pub fn channel_order(&self) -> &'static [Channel] {
+        use Channel::*;
+        match self {
+            PixelFormat::RGB => &[R, G, B],
+            PixelFormat::BGR => &[B, G, R],
+            PixelFormat::RGBX => &[R, G, B, X],
+            PixelFormat::BGRX => &[B, G, R, X],
+            PixelFormat::XBGR => &[X, B, G, R],
+            PixelFormat::XRGB => &[X, R, G, B],
+            PixelFormat::GRAY => &[Gray],
+            PixelFormat::RGBA => &[R, G, B, A],
+            PixelFormat::BGRA => &[B, G, R, A],
+            PixelFormat::ABGR => &[A, B, G, R],
+            PixelFormat::ARGB => &[A, R, G, B],
+            PixelFormat::CMYK => &[C, M, Y, K],
+        }
+    }
+}
+
+/// Meaning of a single byte in a pixel, returned by [`PixelFormat::channel_order()`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Channel {
+    /// Red component.
+    R,
+    /// Green component.
+    G,
+    /// Blue component.
+    B,
+    /// Alpha component, which is always 0xFF when decompressing.
+    A,
+    /// Unused byte, which is ignored when compressing and undefined when decompressing.
+    X,
+    /// Luminance of a grayscale pixel.
+    Gray,
+    /// Cyan component.
+    C,
+    /// Magenta component.
+    M,
+    /// Yellow component.
+    Y,
+    /// Key (black) component.
+    K,
 }
 
 
@@ -182,6 +241,39 @@ pub enum Subsamp {
     /// # Note
     ///
     /// 4:1:1 subsampling is not fully accelerated in libjpeg-turbo.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // vertical stripes of red and blue, each 4 pixels wide, so that every 4x1 block of pixels
+    /// // has a single color
+    /// let width = 64;
+    /// let pixels = (0..width * 32)
+    ///     .flat_map(|i| if i % width % 8 < 4 { [255, 0, 0] } else { [0, 0, 255] })
+    ///     .collect::<Vec<u8>>();
+    /// let image = turbojpeg::Image::new(&pixels[..], width, 32, turbojpeg::PixelFormat::RGB);
+    /// let jpeg_data = turbojpeg::compress(image, 100, turbojpeg::Subsamp::Sub4x1)?;
+    /// assert_eq!(turbojpeg::read_header(&jpeg_data)?.subsamp, turbojpeg::Subsamp::Sub4x1);
+    ///
+    /// // the chrominance planes have one sample for every 4 pixels in a row
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let yuv = decompressor.decompress_to_yuv(&jpeg_data, 1)?;
+    /// assert_eq!((yuv.plane(1).width, yuv.plane(1).height), (16, 32));
+    ///
+    /// // each chrominance sample covers exactly one stripe, so the colors do not bleed into the
+    /// // neighboring stripes, not even at the edges of the stripes
+    /// let decompressed = turbojpeg::decompress(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+    /// for x in 0..width {
+    ///     let [r, _, b] = [0, 1, 2].map(|c| decompressed.pixels[10 * 3 * width + 3 * x + c]);
+    ///     if x % 8 < 4 {
+    ///         assert!(r > 200 && b < 55, "pixel {} should be red, got {:?}", x, (r, b));
+    ///     } else {
+    ///         assert!(r < 55 && b > 200, "pixel {} should be blue, got {:?}", x, (r, b));
+    ///     }
+    /// }
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
     #[doc(alias = "TJSAMP_411")]
     Sub4x1 = raw::TJSAMP_TJSAMP_411,
 }
@@ -199,6 +291,34 @@ impl Subsamp {
         })
     }
 
+    /// Returns the canonical name of this level of chrominance subsampling.
+    ///
+    /// The names are `"4:4:4"`, `"4:2:2"`, `"4:2:0"`, `"gray"`, `"4:4:0"` and `"4:1:1"`, as used
+    /// by the TurboJPEG tools. They can be parsed back using [`str::parse()`], which also accepts
+    /// the luminance sampling factors used by `cjpeg -sample` (such as `"2x2"` for 4:2:0).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use turbojpeg::Subsamp;
+    /// assert_eq!(Subsamp::Sub2x2.as_str(), "4:2:0");
+    /// assert_eq!("4:2:2".parse::<Subsamp>()?, Subsamp::Sub2x1);
+    /// assert_eq!("2x2".parse::<Subsamp>()?, Subsamp::Sub2x2);
+    /// assert_eq!("GRAY".parse::<Subsamp>()?, Subsamp::Gray);
+    /// assert!("4:2:1".parse::<Subsamp>().is_err());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Subsamp::None => "4:4:4",
+            Subsamp::Sub2x1 => "4:2:2",
+            Subsamp::Sub2x2 => "4:2:0",
+            Subsamp::Gray => "gray",
+            Subsamp::Sub1x2 => "4:4:0",
+            Subsamp::Sub4x1 => "4:1:1",
+        }
+    }
+
     /// Get the width of the MCU block for this level of chrominance subsampling.
     #[doc(alias = "tjMCUWidth")]
     pub fn mcu_width(self) -> usize {
@@ -226,6 +346,29 @@ impl Subsamp {
     }
 }
 
+impl std::fmt::Display for Subsamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Subsamp {
+    type Err = Error;
+
+    /// Parses the names returned by [`Subsamp::as_str()`] (ignoring case) and the luminance
+    /// sampling factors used by `cjpeg -sample` (`"1x1"`, `"2x1"`, `"2x2"`, `"1x2"` and `"4x1"`).
+    fn from_str(s: &str) -> Result<Subsamp> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "4:4:4" | "1x1" => Subsamp::None,
+            "4:2:2" | "2x1" => Subsamp::Sub2x1,
+            "4:2:0" | "2x2" => Subsamp::Sub2x2,
+            "gray" | "grey" => Subsamp::Gray,
+            "4:4:0" | "1x2" => Subsamp::Sub1x2,
+            "4:1:1" | "4x1" => Subsamp::Sub4x1,
+            _ => return Err(Error::UnknownSubsamp(s.into())),
+        })
+    }
+}
 
 /// JPEG colorspaces.
 #[doc(alias = "TJCS")]
@@ -298,16 +441,212 @@ impl Colorspace {
     }
 }
 
+/// Discrete cosine transform (DCT) algorithm.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum DctMethod {
+    /// Accurate integer DCT (`JDCT_ISLOW` in libjpeg).
+    ///
+    /// This is the default.
+    #[doc(alias = "TJFLAG_ACCURATEDCT")]
+    #[default]
+    Accurate,
+
+    /// Fast integer DCT (`JDCT_IFAST` in libjpeg).
+    ///
+    /// This is faster than [`Accurate`][DctMethod::Accurate], but it is less accurate and the
+    /// difference becomes noticeable at high quality levels (above 90). On modern CPUs with SIMD,
+    /// the speedup is usually small.
+    #[doc(alias = "TJFLAG_FASTDCT")]
+    Fast,
+}
+
+impl DctMethod {
+    pub(crate) fn flags(self) -> libc::c_int {
+        match self {
+            DctMethod::Accurate => raw::TJFLAG_ACCURATEDCT as libc::c_int,
+            DctMethod::Fast => raw::TJFLAG_FASTDCT as libc::c_int,
+        }
+    }
+}
+
+/// Method of upsampling the chrominance components during decompression.
+///
+/// This matters only for images with [chrominance subsampling][Subsamp], where the chrominance
+/// components have lower resolution than the image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Upsampling {
+    /// Fast upsampling that replicates each chrominance sample (libjpeg "merged" or "box"
+    /// upsampling).
+    ///
+    /// This is faster than [`Smooth`][Upsampling::Smooth], but the chrominance looks blocky near
+    /// sharp color edges.
+    #[doc(alias = "TJFLAG_FASTUPSAMPLE")]
+    Fast,
+
+    /// Smooth upsampling that interpolates between neighboring chrominance samples (libjpeg
+    /// "fancy" upsampling).
+    ///
+    /// This is the default.
+    #[default]
+    Smooth,
+}
+
+impl Upsampling {
+    pub(crate) fn flags(self) -> libc::c_int {
+        match self {
+            Upsampling::Fast => raw::TJFLAG_FASTUPSAMPLE as libc::c_int,
+            Upsampling::Smooth => 0,
+        }
+    }
+}
+
+/// Unit of the pixel density stored in the JFIF APP0 marker.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub enum DensityUnit {
+    /// No unit, the density only specifies the aspect ratio of the pixels.
+    None = 0,
+    /// Dots (pixels) per inch.
+    DotsPerInch = 1,
+    /// Dots (pixels) per centimeter.
+    DotsPerCm = 2,
+}
+
+impl DensityUnit {
+    pub(crate) fn from_u8(unit: u8) -> Option<DensityUnit> {
+        Some(match unit {
+            0 => DensityUnit::None,
+            1 => DensityUnit::DotsPerInch,
+            2 => DensityUnit::DotsPerCm,
+            _ => return None,
+        })
+    }
+}
+
+/// Pixel density stored in the JFIF APP0 marker.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Density {
+    /// Horizontal pixel density.
+    pub x: u16,
+    /// Vertical pixel density.
+    pub y: u16,
+    /// Unit of the density.
+    pub unit: DensityUnit,
+}
+
+/// Entropy coding process of a JPEG image, as given by its SOF (start of frame) marker.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EntropyCoding {
+    /// Sequential DCT with Huffman coding (SOF0 for baseline, SOF1 for extended sequential).
+    ///
+    /// This is the most widely supported kind of JPEG.
+    BaselineHuffman,
+
+    /// Progressive DCT with Huffman coding (SOF2).
+    ///
+    /// The image is stored in multiple scans, so it can be displayed at increasing quality while it
+    /// is being loaded. Such images are produced by
+    /// [`Compressor::set_progressive()`][crate::Compressor::set_progressive] or by a lossless
+    /// transform with [`Transform::progressive`][crate::Transform::progressive] set.
+    ProgressiveHuffman,
+
+    /// Sequential or progressive DCT with arithmetic coding (SOF9, SOF10).
+    ///
+    /// Arithmetic coding produces slightly smaller files than Huffman coding, but it is not
+    /// supported by many decoders.
+    Arithmetic,
+
+    /// Lossless coding (SOF3 or SOF11).
+    ///
+    /// Lossless JPEG is mostly used in medical imaging and it cannot be decompressed by TurboJPEG.
+    Lossless,
+}
+
+impl EntropyCoding {
+    pub(crate) fn from_sof(marker: u8) -> Option<EntropyCoding> {
+        Some(match marker {
+            // SOF5 and SOF6 are the hierarchical variants of SOF1 and SOF2, and SOF13 to SOF15 are
+            // the hierarchical variants of SOF9 to SOF11
+            0xc0 | 0xc1 | 0xc5 => EntropyCoding::BaselineHuffman,
+            0xc2 | 0xc6 => EntropyCoding::ProgressiveHuffman,
+            0xc9 | 0xca | 0xcd | 0xce => EntropyCoding::Arithmetic,
+            0xc3 | 0xc7 | 0xcb | 0xcf => EntropyCoding::Lossless,
+            _ => return None,
+        })
+    }
+}
+
+// The option enums are often used as keys in maps and sets or in sorted configuration, so make sure
+// that they keep implementing these traits.
+const _: () = {
+    const fn assert_key<T: Copy + Eq + Ord + std::hash::Hash + std::fmt::Debug>() {}
+    assert_key::<PixelFormat>();
+    assert_key::<Channel>();
+    assert_key::<Subsamp>();
+    assert_key::<Colorspace>();
+    assert_key::<EntropyCoding>();
+    assert_key::<DctMethod>();
+    assert_key::<Upsampling>();
+    assert_key::<ErrorSeverity>();
+    assert_key::<DensityUnit>();
+    assert_key::<crate::CompressionProfile>();
+    assert_key::<crate::MpoKind>();
+    assert_key::<crate::TransformOp>();
+};
+
+
+/// Severity of an [`Error::TurboJpegError`].
+///
+/// # Example
+///
+/// ```
+/// let image = turbojpeg::Image::mandelbrot(64, 64, turbojpeg::PixelFormat::RGB);
+/// let jpeg_data = turbojpeg::compress(image.as_deref(), 90, turbojpeg::Subsamp::None)?;
+///
+/// // insert garbage after the SOI marker, which libjpeg skips with a warning
+/// let mut corrupt_data = jpeg_data.to_vec();
+/// corrupt_data.splice(2..2, [0x12, 0x34]);
+///
+/// let mut decompressor = turbojpeg::Decompressor::new()?;
+/// let pixels = vec![0; 3 * 64 * 64];
+/// let mut output = turbojpeg::Image::new(pixels, 64, 64, turbojpeg::PixelFormat::RGB);
+/// match decompressor.decompress(&corrupt_data, output.as_deref_mut()) {
+///     Err(turbojpeg::Error::TurboJpegError(_, turbojpeg::ErrorSeverity::Warning)) => {
+///         // the image was decompressed anyway
+///     },
+///     other => panic!("expected a warning, got {:?}", other),
+/// }
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[doc(alias = "TJERR")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ErrorSeverity {
+    /// The error was recoverable, TurboJPEG finished the operation.
+    ///
+    /// This is usually caused by corrupt data in the JPEG image, which libjpeg skipped over. A
+    /// decompressed image is usable, but some parts of it may be wrong.
+    #[doc(alias = "TJERR_WARNING")]
+    Warning,
+
+    /// The error was fatal and the operation did not complete.
+    #[doc(alias = "TJERR_FATAL")]
+    Fatal,
+}
 
 /// Specialized `Result` type for TurboJPEG.
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// An error that can occur in TurboJPEG.
 #[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
 pub enum Error {
-    /// TurboJPEG returned an error message.
+    /// TurboJPEG returned an error message, with the severity of the error.
+    ///
+    /// If the severity is [`ErrorSeverity::Warning`], the operation completed, but the data was
+    /// not entirely correct (please see [`ErrorSeverity`]).
     #[error("TurboJPEG error: {0}")]
-    TurboJpegError(String),
+    TurboJpegError(String, ErrorSeverity),
     
     /// TurboJPEG unexpectedly returned a null pointer, prehaps because it ran out of memory.
     #[error("TurboJPEG returned null pointer")]
@@ -317,16 +656,130 @@ pub enum Error {
     #[error("TurboJPEG returned unknown subsampling option: {0}")]
     BadSubsamp(u32),
 
+    /// The JPEG data ended before the whole image could be decoded.
+    ///
+    /// The header of the image is valid, so the data was most likely cut off (for example, by an
+    /// incomplete upload or download). This is returned instead of
+    /// [`TurboJpegError`][Error::TurboJpegError] so that you can distinguish truncated images from
+    /// data that is not a JPEG at all.
+    #[error("JPEG data is truncated after {bytes_available} bytes")]
+    Truncated {
+        /// Number of bytes of JPEG data that were available.
+        bytes_available: usize,
+        /// Rough estimate of the size of the complete JPEG data, if it can be determined from the
+        /// number of rows that were decoded. This is `None` if no rows could be decoded or for
+        /// progressive JPEGs, whose first scans cover the whole image.
+        bytes_expected_hint: Option<usize>,
+        /// Severity of the TurboJPEG error that was caused by the truncation. If it is
+        /// [`ErrorSeverity::Warning`], the rows before the end of the data were decoded.
+        severity: ErrorSeverity,
+    },
+
+    /// The image has more pixels than the limit set by
+    /// [`Decompressor::set_max_pixels()`][crate::Decompressor::set_max_pixels].
+    #[error("image with {width}x{height} pixels exceeds the limit of {max_pixels} pixels")]
+    TooManyPixels {
+        /// Width of the image in pixels.
+        width: usize,
+        /// Height of the image in pixels.
+        height: usize,
+        /// The maximal number of pixels that is allowed.
+        max_pixels: usize,
+    },
+
+    /// The output buffer is too small for the JPEG data.
+    #[error("output buffer is too small, {0} bytes are needed")]
+    OutputTooSmall(usize),
+
+    /// The data does not fit into a single JPEG marker, which can hold at most 65533 bytes
+    /// (including the signature).
+    #[error("data with {0} bytes does not fit into a JPEG marker")]
+    MarkerTooLarge(usize),
+
+    /// The given chrominance sampling factors do not correspond to any subsampling option
+    /// supported by TurboJPEG.
+    #[error("unsupported sampling factors {0}x{1}")]
+    BadSamplingFactors(u8, u8),
+
+    /// The pixel density is zero.
+    #[error("density {0}x{1} must not be zero")]
+    BadDensity(u16, u16),
+
+    /// The rotation angle in degrees is not a multiple of 90.
+    #[error("rotation by {0} degrees is not a multiple of 90 degrees")]
+    BadRotation(i32),
+
+    /// The alpha mask does not have the same size (width, height) as the color image.
+    #[error("alpha mask with size {1:?} does not match color image with size {0:?}")]
+    AlphaSizeMismatch((usize, usize), (usize, usize)),
+
+    /// Pixels cannot be converted between the two pixel formats by
+    /// [`convert_pixels()`][crate::convert_pixels].
+    #[error("cannot convert pixels from {0:?} to {1:?}")]
+    UnsupportedConversion(PixelFormat, PixelFormat),
+
+    /// The string could not be parsed as a [`Subsamp`].
+    #[error("unknown subsampling option: {0:?}")]
+    UnknownSubsamp(String),
+
     /// TurboJPEG returned a colorspace variant that is not known by this crate.
     #[error("TurboJPEG returned unknown colorspace: {0}")]
     BadColorspace(u32),
 
+    /// TurboJPEG did not behave as expected in the self-test of [`init()`][crate::init].
+    #[error("TurboJPEG self-test failed: {0}")]
+    SelfTestFailed(String),
+
+    /// The JPEG header could not be parsed.
+    #[error("invalid JPEG header: {0}")]
+    BadHeader(&'static str),
+
     /// The given integer value overflowed when converted into type expected by TurboJPEG.
     #[error("integer value {0:?} overflowed")]
     IntegerOverflow(&'static str),
+
+    /// An I/O operation failed.
+    ///
+    /// The underlying [`std::io::Error`] is available as the error
+    /// [`source()`][std::error::Error::source]. This error can be created from [`std::io::Error`]
+    /// using `?` or `.into()`.
+    #[error("I/O error")]
+    Io(#[from] std::io::Error),
+
+    /// The [`image`][image-rs] crate returned an error.
+    ///
+    /// The underlying [`image::ImageError`][::image::ImageError] is available as the error
+    /// [`source()`][std::error::Error::source]. This error can be created from
+    /// [`image::ImageError`][::image::ImageError] using `?` or `.into()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // a helper that reads a file in any format supported by image-rs and compresses it to JPEG
+    /// fn transcode(path: &str) -> turbojpeg::Result<Vec<u8>> {
+    ///     let bytes = std::fs::read(path)?; // std::io::Error
+    ///     let image = image::load_from_memory(&bytes)?.into_rgb8(); // image::ImageError
+    ///     Ok(turbojpeg::compress_image(&image, 90, turbojpeg::Subsamp::Sub2x2)?.to_vec())
+    /// }
+    ///
+    /// assert!(matches!(transcode("does-not-exist.png"), Err(turbojpeg::Error::Io(_))));
+    /// let jpeg_data = transcode("examples/parrots.jpg")?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// [image-rs]: https://docs.rs/image/*/image/index.html
+    #[cfg(feature = "image")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+    #[error("image error")]
+    Image(#[from] image::ImageError),
 }
 
 pub(crate) unsafe fn get_error(handle: raw::tjhandle) -> Error {
     let msg = CStr::from_ptr(raw::tjGetErrorStr2(handle));
-    Error::TurboJpegError(msg.to_string_lossy().into_owned())
+    let severity = if raw::tjGetErrorCode(handle) == raw::TJERR_TJERR_WARNING as libc::c_int {
+        ErrorSeverity::Warning
+    } else {
+        ErrorSeverity::Fatal
+    };
+    Error::TurboJpegError(msg.to_string_lossy().into_owned(), severity)
 }
@@ -1,17 +1,93 @@
 use std::convert::TryInto as _;
 use crate::{Image, raw};
 use crate::buf::{OwnedBuf, OutputBuf};
-use crate::common::{Subsamp, Result, Error, get_error};
+use crate::common::{Colorspace, DctMethod, DensityUnit, PixelFormat, Subsamp, Result, Error, get_error};
+use crate::marker;
+use crate::metrics::{self, Operation};
 
 /// Compresses raw pixel data into JPEG.
+///
+/// # Restart intervals
+///
+/// The TurboJPEG 2.1 API has no parameter for the restart interval, so the compressor does not
+/// have a setter for it. However, TurboJPEG reads the `TJ_RESTART` environment variable in every
+/// compression: `TJ_RESTART=N` inserts a restart marker every `N` MCU rows and `TJ_RESTART=NB`
+/// every `N` MCU blocks. The variable affects all compressions in the process, so set it before
+/// you start any threads that compress images.
+///
+/// ```
+/// let image = turbojpeg::Image::mandelbrot(64, 64, turbojpeg::PixelFormat::RGB);
+/// let mut compressor = turbojpeg::Compressor::new()?;
+/// let restart_interval = |jpeg_data: &[u8]| -> turbojpeg::Result<Option<u16>> {
+///     for marker in turbojpeg::markers(jpeg_data) {
+///         let marker = marker?;
+///         if marker.kind == 0xdd {
+///             let dri = &jpeg_data[marker.offset + 4..][..2];
+///             return Ok(Some(u16::from_be_bytes([dri[0], dri[1]])))
+///         }
+///     }
+///     Ok(None)
+/// };
+///
+/// // a restart marker after every 4 MCU blocks
+/// std::env::set_var("TJ_RESTART", "4B");
+/// let jpeg_data = compressor.compress_to_vec(image.as_deref())?;
+/// assert_eq!(restart_interval(&jpeg_data)?, Some(4));
+///
+/// // a restart marker after every MCU row, which has 8 blocks in this image
+/// std::env::set_var("TJ_RESTART", "1");
+/// let jpeg_data = compressor.compress_to_vec(image.as_deref())?;
+/// assert_eq!(restart_interval(&jpeg_data)?, Some(8));
+///
+/// std::env::remove_var("TJ_RESTART");
+/// let jpeg_data = compressor.compress_to_vec(image.as_deref())?;
+/// assert_eq!(restart_interval(&jpeg_data)?, None);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
 #[derive(Debug)]
 #[doc(alias = "tjhandle")]
+#[doc(alias = "TJ_RESTART")]
 pub struct Compressor {
     handle: raw::tjhandle,
     quality: i32,
     subsamp: Subsamp,
+    progressive: bool,
+    dct_method: DctMethod,
+    raw_flags: libc::c_int,
+    exif: Option<Vec<u8>>,
+    density: Option<(u16, u16, DensityUnit)>,
+}
+
+/// Preset of compression parameters, applied by [`Compressor::apply_profile()`].
+///
+/// Each profile sets the following parameters of the [`Compressor`], and leaves all other
+/// parameters (including the quality) unchanged:
+///
+/// | Profile | DCT method | Progressive | Subsampling |
+/// |---|---|---|---|
+/// | [`Fastest`][Self::Fastest] | [`Fast`][DctMethod::Fast] | no | [`Sub2x2`][Subsamp::Sub2x2] (4:2:0) |
+/// | [`Balanced`][Self::Balanced] | [`Accurate`][DctMethod::Accurate] | no | [`Sub2x1`][Subsamp::Sub2x1] (4:2:2) |
+/// | [`SmallestFile`][Self::SmallestFile] | [`Accurate`][DctMethod::Accurate] | yes | [`Sub2x2`][Subsamp::Sub2x2] (4:2:0) |
+/// | [`WebDefault`][Self::WebDefault] | [`Fast`][DctMethod::Fast] | yes | [`Sub2x2`][Subsamp::Sub2x2] (4:2:0) |
+///
+/// TurboJPEG always uses optimized Huffman tables for progressive images and the default tables
+/// otherwise, so there is no separate setting for that. You can override any of the parameters
+/// after applying the profile.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CompressionProfile {
+    /// Compress as fast as possible.
+    Fastest,
+    /// Good quality with reasonable size and speed.
+    Balanced,
+    /// Produce the smallest files, at the cost of slower compression and decompression.
+    SmallestFile,
+    /// Small files that load progressively in web browsers, compressed fast enough for serving
+    /// images on the fly.
+    WebDefault,
 }
 
+const EXIF_SIGNATURE: &[u8] = b"Exif\0\0";
+
 static DEFAULT_QUALITY: i32 = 95;
 static DEFAULT_SUBSAMP: Subsamp = Subsamp::None;
 
@@ -28,6 +104,11 @@ impl Compressor {
                     handle,
                     quality: DEFAULT_QUALITY,
                     subsamp: DEFAULT_SUBSAMP,
+                    progressive: false,
+                    dct_method: DctMethod::default(),
+                    raw_flags: 0,
+                    exif: None,
+                    density: None,
                 })
             } else {
                 Err(get_error(handle))
@@ -35,6 +116,41 @@ impl Compressor {
         }
     }
 
+    /// Restore all compression parameters to their defaults.
+    ///
+    /// This resets the quality, subsampling, progressive coding, DCT method, raw flags, EXIF
+    /// metadata and density to the values used by a new compressor, but keeps the TurboJPEG
+    /// instance, so it is cheaper than creating a new compressor. Use this method before reusing a
+    /// compressor (for example, from a pool) for an unrelated image.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let image = turbojpeg::Image::mandelbrot(200, 100, turbojpeg::PixelFormat::RGB);
+    ///
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// compressor.set_quality(50);
+    /// compressor.set_progressive(true);
+    /// compressor.set_subsamp(turbojpeg::Subsamp::Sub2x2);
+    /// compressor.compress_to_vec(image.as_deref())?;
+    ///
+    /// // after a reset, the compressor produces the same output as a new one
+    /// compressor.reset();
+    /// let reset_data = compressor.compress_to_vec(image.as_deref())?;
+    /// let fresh_data = turbojpeg::Compressor::new()?.compress_to_vec(image.as_deref())?;
+    /// assert_eq!(reset_data, fresh_data);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn reset(&mut self) {
+        self.quality = DEFAULT_QUALITY;
+        self.subsamp = DEFAULT_SUBSAMP;
+        self.progressive = false;
+        self.dct_method = DctMethod::default();
+        self.raw_flags = 0;
+        self.exif = None;
+        self.density = None;
+    }
+
     /// Set the quality of the compressed JPEG images.
     ///
     /// The quality ranges from 1 (worst) to 100 (best).
@@ -43,14 +159,232 @@ impl Compressor {
         self.quality = quality;
     }
 
+    /// Get the quality set by [`set_quality()`](Compressor::set_quality).
+    ///
+    /// The default is 95.
+    pub fn quality(&self) -> i32 {
+        self.quality
+    }
+
     /// Set the level of chrominance subsampling of the compressed JPEG images.
     ///
     /// Chrominance subsampling can reduce the compressed image size without noticeable loss of
     /// quality (see [`Subsamp`] for more).
+    ///
+    /// Like all other parameters, the subsampling is stored in the compressor and used for all
+    /// images that it compresses, until you change it or [`reset()`](Compressor::reset) the
+    /// compressor. The free functions such as [`compress()`][crate::compress()] take the
+    /// subsampling as an argument only because they create a new compressor for every call.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// assert_eq!(compressor.subsamp(), turbojpeg::Subsamp::None);
+    /// compressor.set_subsamp(turbojpeg::Subsamp::Sub2x2);
+    ///
+    /// // the subsampling applies to every image compressed by `compressor`
+    /// for size in [100, 200] {
+    ///     let image = turbojpeg::Image::mandelbrot(size, size, turbojpeg::PixelFormat::RGB);
+    ///     let jpeg_data = compressor.compress_to_vec(image.as_deref())?;
+    ///     assert_eq!(turbojpeg::read_header(&jpeg_data)?.subsamp, turbojpeg::Subsamp::Sub2x2);
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
     pub fn set_subsamp(&mut self, subsamp: Subsamp) {
         self.subsamp = subsamp;
     }
 
+    /// Get the chrominance subsampling set by [`set_subsamp()`](Compressor::set_subsamp) or
+    /// [`set_sampling_factors()`](Compressor::set_sampling_factors).
+    ///
+    /// The default is [`Subsamp::None`].
+    pub fn subsamp(&self) -> Subsamp {
+        self.subsamp
+    }
+
+    /// Set the chrominance subsampling from luminance sampling factors.
+    ///
+    /// `h` and `v` are the horizontal and vertical sampling factors of the luminance component,
+    /// while both chrominance components use factors 1x1 (this is the notation used by `cjpeg
+    /// -sample`, so `2x2` means 4:2:0). TurboJPEG only supports the combinations that correspond
+    /// to one of the [`Subsamp`] options (1x1, 2x1, 2x2, 1x2 and 4x1), so this method returns an
+    /// error for any other combination.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// compressor.set_sampling_factors(2, 1)?; // same as Subsamp::Sub2x1 (4:2:2)
+    /// assert!(compressor.set_sampling_factors(3, 1).is_err());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_sampling_factors(&mut self, h: u8, v: u8) -> Result<()> {
+        let subsamp = [Subsamp::None, Subsamp::Sub2x1, Subsamp::Sub2x2, Subsamp::Sub1x2, Subsamp::Sub4x1]
+            .into_iter()
+            .find(|subsamp| subsamp.mcu_size() == (8 * h as usize, 8 * v as usize))
+            .ok_or(Error::BadSamplingFactors(h, v))?;
+        self.subsamp = subsamp;
+        Ok(())
+    }
+
+    /// Enable or disable progressive entropy coding of the compressed JPEG images.
+    ///
+    /// Progressive JPEG images are generally smaller than baseline images (the default) and they
+    /// can be displayed at increasing quality while they are being loaded, but they are slower to
+    /// compress and decompress.
+    #[doc(alias = "TJFLAG_PROGRESSIVE")]
+    pub fn set_progressive(&mut self, progressive: bool) {
+        self.progressive = progressive;
+    }
+
+    /// Set the DCT algorithm that is used for compression.
+    ///
+    /// The default is [`DctMethod::Accurate`].
+    pub fn set_dct_method(&mut self, dct_method: DctMethod) {
+        self.dct_method = dct_method;
+    }
+
+    /// Set several compression parameters at once using a preset.
+    ///
+    /// Please see [`CompressionProfile`] for the parameters that each profile sets.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// compressor.apply_profile(turbojpeg::CompressionProfile::WebDefault);
+    /// compressor.set_quality(80);
+    ///
+    /// let image = turbojpeg::Image::mandelbrot(300, 200, turbojpeg::PixelFormat::RGB);
+    /// let jpeg_data = compressor.compress_to_vec(image.as_deref())?;
+    /// let header = turbojpeg::read_header(&jpeg_data)?;
+    /// assert_eq!(header.entropy_coding, turbojpeg::EntropyCoding::ProgressiveHuffman);
+    /// assert_eq!(header.subsamp, turbojpeg::Subsamp::Sub2x2);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn apply_profile(&mut self, profile: CompressionProfile) {
+        let (dct_method, progressive, subsamp) = match profile {
+            CompressionProfile::Fastest => (DctMethod::Fast, false, Subsamp::Sub2x2),
+            CompressionProfile::Balanced => (DctMethod::Accurate, false, Subsamp::Sub2x1),
+            CompressionProfile::SmallestFile => (DctMethod::Accurate, true, Subsamp::Sub2x2),
+            CompressionProfile::WebDefault => (DctMethod::Fast, true, Subsamp::Sub2x2),
+        };
+        self.dct_method = dct_method;
+        self.progressive = progressive;
+        self.subsamp = subsamp;
+    }
+
+    /// Set raw TurboJPEG flags that are passed to `tjCompress2()`.
+    ///
+    /// This is an escape hatch for the flags that this crate does not wrap, such as
+    /// [`TJFLAG_ACCURATEDCT`][raw::TJFLAG_ACCURATEDCT] or
+    /// [`TJFLAG_PROGRESSIVE`][raw::TJFLAG_PROGRESSIVE] (the `TJFLAG_*` constants are available in
+    /// the [`raw`] module). The flags are combined with the flags that this crate sets itself, and
+    /// [`TJFLAG_NOREALLOC`][raw::TJFLAG_NOREALLOC] is ignored, because it is determined from the
+    /// output buffer. Prefer the typed setters where they exist.
+    ///
+    /// The default is 0 (no flags).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// compressor.set_raw_flags(turbojpeg::raw::TJFLAG_PROGRESSIVE as i32);
+    /// assert_eq!(compressor.raw_flags(), turbojpeg::raw::TJFLAG_PROGRESSIVE as i32);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_raw_flags(&mut self, flags: i32) {
+        self.raw_flags = flags & !(raw::TJFLAG_NOREALLOC as libc::c_int);
+    }
+
+    /// Get the raw TurboJPEG flags set by [`set_raw_flags()`](Compressor::set_raw_flags).
+    pub fn raw_flags(&self) -> i32 {
+        self.raw_flags
+    }
+
+    /// Set the EXIF metadata that is written into the compressed JPEG images.
+    ///
+    /// The `exif` data (a TIFF structure, which starts with `II` or `MM`) is written into an APP1
+    /// marker with the `Exif\0\0` signature, which is added if `exif` does not already start
+    /// with it. The marker is placed after the JFIF marker at the start of the image. Pass an empty
+    /// slice to stop writing EXIF metadata.
+    ///
+    /// Returns an error if the data does not fit into a single marker (about 64 KB). The size of
+    /// the marker is included in [`buf_len()`](Compressor::buf_len).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // EXIF data from another image (here, a minimal TIFF header without any tags)
+    /// let exif = b"MM\x00\x2a\x00\x00\x00\x08\x00\x00";
+    ///
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// compressor.set_exif(exif)?;
+    ///
+    /// let image = turbojpeg::Image::mandelbrot(64, 64, turbojpeg::PixelFormat::RGB);
+    /// let jpeg_data = compressor.compress_to_vec(image.as_deref())?;
+    /// assert!(jpeg_data.windows(6).any(|w| w == b"Exif\0\0"));
+    ///
+    /// // the data must fit into a single marker
+    /// assert!(compressor.set_exif(&vec![0; 70000]).is_err());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_exif(&mut self, exif: &[u8]) -> Result<()> {
+        if exif.is_empty() {
+            self.exif = None;
+            return Ok(())
+        }
+
+        let exif = exif.strip_prefix(EXIF_SIGNATURE).unwrap_or(exif);
+        let segment_len = 2 + EXIF_SIGNATURE.len() + exif.len();
+        let segment_len_u16 = u16::try_from(segment_len)
+            .map_err(|_| Error::MarkerTooLarge(EXIF_SIGNATURE.len() + exif.len()))?;
+
+        let mut marker = Vec::with_capacity(2 + segment_len);
+        marker.extend_from_slice(&[0xff, marker::APP1]);
+        marker.extend_from_slice(&segment_len_u16.to_be_bytes());
+        marker.extend_from_slice(EXIF_SIGNATURE);
+        marker.extend_from_slice(exif);
+        self.exif = Some(marker);
+        Ok(())
+    }
+
+    /// Set the pixel density that is written into the JFIF APP0 marker of the compressed images.
+    ///
+    /// The density tells printing and layout software the physical size of the image. By default,
+    /// TurboJPEG writes a density of 1x1 without a unit (square pixels, but unknown size), which
+    /// many programs interpret as 72 or 96 DPI. `x` and `y` are the horizontal and vertical
+    /// density; this method returns an error if either of them is zero.
+    ///
+    /// TurboJPEG does not write the JFIF marker into CMYK images, so the density is not written
+    /// into them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// compressor.set_density(300, 300, turbojpeg::DensityUnit::DotsPerInch)?;
+    ///
+    /// let image = turbojpeg::Image::mandelbrot(64, 64, turbojpeg::PixelFormat::RGB);
+    /// let jpeg_data = compressor.compress_to_vec(image.as_deref())?;
+    ///
+    /// // the unit and the density are stored at fixed offsets in the JFIF marker
+    /// assert_eq!(&jpeg_data[6..11], b"JFIF\0");
+    /// assert_eq!(&jpeg_data[13..18], &[1, 0x01, 0x2c, 0x01, 0x2c]);
+    ///
+    /// // the density must not be zero
+    /// assert!(compressor.set_density(0, 300, turbojpeg::DensityUnit::DotsPerInch).is_err());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_density(&mut self, x: u16, y: u16, unit: DensityUnit) -> Result<()> {
+        if x == 0 || y == 0 {
+            return Err(Error::BadDensity(x, y))
+        }
+        self.density = Some((x, y, unit));
+        Ok(())
+    }
+
     /// Compresses the `image` into `output` buffer.
     ///
     /// This is the main compression method, which gives you full control of the output buffer. If
@@ -83,23 +417,41 @@ impl Compressor {
     #[doc(alias = "tjCompress")]
     pub fn compress(&mut self, image: Image<&[u8]>, output: &mut OutputBuf) -> Result<()> {
         image.assert_valid(image.pixels.len());
+        let start = metrics::start();
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!("compress",
+            width = image.width, height = image.height, subsamp = ?self.subsamp,
+            quality = self.quality, output_len = tracing::field::Empty,
+        ).entered();
 
         let Image { pixels, width, pitch, height, format } = image;
+        let (num_pixels, input_len) = (width * height, width * height * format.size());
         let width = width.try_into().map_err(|_| Error::IntegerOverflow("width"))?;
         let pitch = pitch.try_into().map_err(|_| Error::IntegerOverflow("pitch"))?;
         let height = height.try_into().map_err(|_| Error::IntegerOverflow("height"))?;
 
-        let mut output_len = output.len as libc::c_ulong;
+        if !output.is_owned {
+            // TurboJPEG assumes that a buffer that it cannot reallocate is large enough for the
+            // worst case, so we must check that before giving it the slice
+            let required_len = compressed_buf_len(image.width, image.height, self.subsamp)?;
+            if output.cap < required_len {
+                return Err(Error::OutputTooSmall(required_len))
+            }
+        }
+
+        let ptr_before = output.ptr;
+        let mut output_len = output.cap as libc::c_ulong;
         let res = unsafe {
             raw::tjCompress2(
                 self.handle,
                 pixels.as_ptr(), width, pitch, height, format as libc::c_int,
                 &mut output.ptr, &mut output_len,
                 self.subsamp as libc::c_int, self.quality,
-                if output.is_owned { 0 } else { raw::TJFLAG_NOREALLOC } as libc::c_int,
+                self.flags(output.is_owned),
             )
         };
         output.len = output_len as usize;
+        output.update_cap(ptr_before);
 
         if res != 0 {
             Err(unsafe { get_error(self.handle) })
@@ -107,10 +459,27 @@ impl Compressor {
             output.len = 0;
             Err(Error::Null())
         } else {
+            if let Some((x, y, unit)) = self.density {
+                marker::set_jfif_density(output, x, y, unit as u8);
+            }
+            if let Some(exif) = &self.exif {
+                output.insert(marker::app_insert_offset(output), exif)?;
+            }
+            output.apply_growth_policy();
+            metrics::record(start, Operation::Compress, input_len, output.len, num_pixels);
+            #[cfg(feature = "tracing")]
+            span.record("output_len", output.len);
             Ok(())
         }
     }
 
+    fn flags(&self, is_owned: bool) -> libc::c_int {
+        let mut flags = self.raw_flags | self.dct_method.flags();
+        if self.progressive { flags |= raw::TJFLAG_PROGRESSIVE as libc::c_int }
+        if !is_owned { flags |= raw::TJFLAG_NOREALLOC as libc::c_int }
+        flags
+    }
+
     /// Compresses the `image` into an owned buffer.
     ///
     /// This method automatically allocates the memory and avoids needless copying.
@@ -131,26 +500,178 @@ impl Compressor {
         Ok(buf.to_vec())
     }
 
+    /// Compress the `image` into a new `Vec<u8>`, starting with an output buffer of `capacity`
+    /// bytes.
+    ///
+    /// This is the same as [`compress_to_vec()`](Compressor::compress_to_vec), but TurboJPEG
+    /// starts with a buffer of the given size instead of a buffer for the worst case. If you can
+    /// predict the size of the compressed image (for example, from the sizes of the previous
+    /// frames of a video), this avoids reallocating the buffer during compression. If the
+    /// `capacity` is too small, TurboJPEG grows the buffer as usual.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// let mut size_hint = 0;
+    /// for _ in 0..5 {
+    ///     let frame = turbojpeg::Image::mandelbrot(320, 240, turbojpeg::PixelFormat::RGB);
+    ///     let jpeg_data = compressor.compress_to_vec_with_capacity(frame.as_deref(), size_hint)?;
+    ///     // leave some headroom for the next frame
+    ///     size_hint = jpeg_data.len() * 5 / 4;
+    /// }
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn compress_to_vec_with_capacity(
+        &mut self,
+        image: Image<&[u8]>,
+        capacity: usize,
+    ) -> Result<Vec<u8>> {
+        let mut buf = if capacity == 0 {
+            OutputBuf::new_owned()
+        } else {
+            OutputBuf::allocate_owned(capacity)
+        };
+        self.compress(image, &mut buf)?;
+        Ok(buf.to_vec())
+    }
+
     /// Compress the `image` into the slice `output`.
     ///
-    /// Returns the size of the compressed JPEG data. If the compressed image does not fit into
-    /// `dest`, this method returns an error. Use [`buf_len()`](Compressor::buf_len) to determine
-    /// buffer size that is guaranteed to be large enough for the compressed image.
+    /// Returns the size of the compressed JPEG data. The slice must be at least as large as
+    /// [`compressed_buf_len()`] for the image (or [`buf_len()`](Compressor::buf_len), which also
+    /// accounts for the EXIF metadata), otherwise this method returns [`Error::OutputTooSmall`]
+    /// without compressing the image, because TurboJPEG cannot safely write into a smaller
+    /// buffer.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // create an image with 64x64 pixels
+    /// let pixels = vec![128; 3 * 64 * 64];
+    /// let image = turbojpeg::Image {
+    ///     pixels: &pixels[..],
+    ///     width: 64,
+    ///     pitch: 3 * 64,
+    ///     height: 64,
+    ///     format: turbojpeg::PixelFormat::RGB,
+    /// };
+    ///
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    ///
+    /// // a slice that is too small for the worst case is rejected
+    /// let mut output = vec![0; 1000];
+    /// let res = compressor.compress_to_slice(image, &mut output);
+    /// assert!(matches!(res, Err(turbojpeg::Error::OutputTooSmall(_))));
+    ///
+    /// // a slice of the size returned by buf_len() is large enough
+    /// let mut output = vec![0; compressor.buf_len(64, 64)?];
+    /// let len = compressor.compress_to_slice(image, &mut output)?;
+    /// assert_eq!(&output[..2], &[0xff, 0xd8]);
+    /// assert!(len <= output.len());
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
     pub fn compress_to_slice(&mut self, image: Image<&[u8]>, output: &mut [u8]) -> Result<usize> {
         let mut buf = OutputBuf::borrowed(output);
         self.compress(image, &mut buf)?;
         Ok(buf.len())
     }
 
+    /// Recompress the JPEG image `jpeg_data` with the parameters of this compressor, but only if
+    /// that makes it smaller.
+    ///
+    /// The image is decompressed and compressed again with the current quality, subsampling and
+    /// other parameters of this compressor. Returns the recompressed JPEG and `true` if it is
+    /// smaller than `jpeg_data`, or a copy of `jpeg_data` and `false` otherwise, so the result is
+    /// never larger than the original.
+    ///
+    /// Grayscale images are decompressed to [`PixelFormat::GRAY`] and always recompressed with
+    /// [`Subsamp::Gray`], because libjpeg cannot convert grayscale pixels into color. If the
+    /// subsampling of this compressor is [`Subsamp::Gray`], color images are also decompressed to
+    /// [`PixelFormat::GRAY`], so the recompressed image is grayscale. Otherwise, CMYK and YCCK
+    /// images are decompressed to [`PixelFormat::CMYK`] and all other images to
+    /// [`PixelFormat::RGB`]. CMYK and YCCK images cannot be converted into grayscale, so
+    /// recompressing them with [`Subsamp::Gray`] fails with [`Error::UnsupportedConversion`].
+    ///
+    /// Note that the markers of the original image (such as EXIF metadata) are not copied into the
+    /// recompressed image, you need to set them on the compressor (for example, using
+    /// [`set_exif()`][Self::set_exif]).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// // recompressing with low quality makes the image smaller
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// compressor.set_quality(50);
+    /// compressor.set_subsamp(turbojpeg::Subsamp::Sub2x2);
+    /// let (smaller_data, recompressed) = compressor.recompress_if_smaller(&jpeg_data)?;
+    /// assert!(recompressed);
+    /// assert!(smaller_data.len() < jpeg_data.len());
+    ///
+    /// // recompressing the smaller image with the highest quality would make it larger again, so we
+    /// // keep it unchanged
+    /// compressor.set_quality(100);
+    /// compressor.set_subsamp(turbojpeg::Subsamp::None);
+    /// let (data, recompressed) = compressor.recompress_if_smaller(&smaller_data)?;
+    /// assert!(!recompressed);
+    /// assert_eq!(data, smaller_data);
+    ///
+    /// // grayscale images stay grayscale, regardless of the subsampling of the compressor
+    /// let image = turbojpeg::Image::mandelbrot(384, 256, turbojpeg::PixelFormat::GRAY);
+    /// let gray_data = turbojpeg::compress(image.as_deref(), 100, turbojpeg::Subsamp::Gray)?;
+    /// compressor.set_quality(50);
+    /// let (smaller_data, recompressed) = compressor.recompress_if_smaller(&gray_data)?;
+    /// assert!(recompressed);
+    /// assert_eq!(turbojpeg::read_header(&smaller_data)?.subsamp, turbojpeg::Subsamp::Gray);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn recompress_if_smaller(&mut self, jpeg_data: &[u8]) -> Result<(Vec<u8>, bool)> {
+        let colorspace = crate::read_header(jpeg_data)?.colorspace;
+        let format = match colorspace {
+            Colorspace::Gray => PixelFormat::GRAY,
+            Colorspace::CMYK | Colorspace::YCCK if self.subsamp == Subsamp::Gray =>
+                return Err(Error::UnsupportedConversion(PixelFormat::CMYK, PixelFormat::GRAY)),
+            Colorspace::CMYK | Colorspace::YCCK => PixelFormat::CMYK,
+            _ if self.subsamp == Subsamp::Gray => PixelFormat::GRAY,
+            _ => PixelFormat::RGB,
+        };
+        let image = crate::decompress(jpeg_data, format)?;
+
+        // grayscale pixels can only be compressed into a grayscale JPEG
+        let subsamp = self.subsamp;
+        if format == PixelFormat::GRAY {
+            self.subsamp = Subsamp::Gray;
+        }
+        let mut buf = OutputBuf::new_owned();
+        let res = self.compress(image.as_deref(), &mut buf);
+        self.subsamp = subsamp;
+        res?;
+
+        if buf.len() < jpeg_data.len() {
+            Ok((buf.to_vec(), true))
+        } else {
+            Ok((jpeg_data.to_vec(), false))
+        }
+    }
+
     /// Compute the maximum size of a compressed image.
     ///
     /// This depends on image `width` and `height`, and also on the current setting of chrominance
-    /// subsampling (see [`set_subsamp()`](Compressor::set_subsamp)).
+    /// subsampling (see [`set_subsamp()`](Compressor::set_subsamp)) and on the EXIF metadata (see
+    /// [`set_exif()`](Compressor::set_exif)).
     ///
     /// You can also use [`compressed_buf_len()`] directly.
     #[doc(alias = "tjBufSize")]
     pub fn buf_len(&self, width: usize, height: usize) -> Result<usize> {
-        super::compressed_buf_len(width, height, self.subsamp)
+        let len = super::compressed_buf_len(width, height, self.subsamp)?;
+        let exif_len = self.exif.as_ref().map_or(0, |exif| exif.len());
+        len.checked_add(exif_len).ok_or(Error::IntegerOverflow("buf len"))
     }
 }
 
@@ -187,17 +708,108 @@ pub fn compress(image: Image<&[u8]>, quality: i32, subsamp: Subsamp) -> Result<O
     compressor.compress_to_owned(image)
 }
 
+/// Compress a grayscale image with one byte per pixel into a grayscale JPEG.
+///
+/// The rows of `pixels` must not be padded, so `pixels` contains `width * height` bytes. This is
+/// a shorthand for [`compress()`] with [`PixelFormat::GRAY`] and [`Subsamp::Gray`], so you don't
+/// need to construct an [`Image`]. Use [`decompress_gray()`][crate::decompress_gray] to decompress
+/// the image.
+///
+/// Panics if `pixels` is shorter than `width * height`.
+///
+/// # Example
+///
+/// ```
+/// // create a horizontal gradient
+/// let (width, height) = (256, 64);
+/// let pixels = (0..width * height).map(|i| (i % width) as u8).collect::<Vec<u8>>();
+///
+/// // compress it into a grayscale JPEG
+/// let jpeg_data = turbojpeg::compress_gray(&pixels, width, height, 90)?;
+/// let header = turbojpeg::read_header(&jpeg_data)?;
+/// assert_eq!(header.subsamp, turbojpeg::Subsamp::Gray);
+/// assert_eq!((header.width, header.height), (256, 64));
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn compress_gray(pixels: &[u8], width: usize, height: usize, quality: i32) -> Result<OwnedBuf> {
+    let image = Image::new(pixels, width, height, PixelFormat::GRAY);
+    compress(image, quality, Subsamp::Gray)
+}
+
+/// Options for [`encode()`].
+///
+/// The default options are the same as the defaults of a new [`Compressor`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct EncodeOptions {
+    /// Quality from 1 (worst) to 100 (best), see [`Compressor::set_quality()`].
+    pub quality: i32,
+    /// Chrominance subsampling, see [`Compressor::set_subsamp()`].
+    pub subsamp: Subsamp,
+    /// Progressive entropy coding, see [`Compressor::set_progressive()`].
+    pub progressive: bool,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        EncodeOptions { quality: DEFAULT_QUALITY, subsamp: DEFAULT_SUBSAMP, progressive: false }
+    }
+}
+
+/// Compress an image into JPEG with the given options.
+///
+/// This is a thin wrapper around [`Compressor`] that returns a `Vec<u8>`, which is handy in
+/// property-based tests and fuzzers together with [`decode()`][crate::decode].
+///
+/// # Example
+///
+/// ```
+/// let image = turbojpeg::Image::mandelbrot(40, 30, turbojpeg::PixelFormat::RGB);
+/// let options = turbojpeg::EncodeOptions { quality: 100, ..Default::default() };
+/// let jpeg_data = turbojpeg::encode(image.as_deref(), options)?;
+///
+/// // decoding the JPEG gives us an image of the same size and format
+/// let (pixels, header) = turbojpeg::decode(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+/// assert_eq!((header.width, header.height), (40, 30));
+/// assert_eq!(pixels.len(), image.pixels.len());
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn encode(image: Image<&[u8]>, options: EncodeOptions) -> Result<Vec<u8>> {
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(options.quality);
+    compressor.set_subsamp(options.subsamp);
+    compressor.set_progressive(options.progressive);
+    compressor.compress_to_vec(image)
+}
+
 /// Compute the maximum size of a compressed image.
 ///
 /// This depends on image `width` and `height` and also on the chrominance subsampling method.
 ///
-/// Returns an error on integer overflow (you can just `.unwrap()` the result if you don't care
-/// about this edge case).
+/// The size is computed with the same formula as `tjBufSize()` in TurboJPEG, but with checked
+/// arithmetic, so it returns an error instead of a size that silently overflowed (you can just
+/// `.unwrap()` the result if you don't care about this edge case). Dimensions that TurboJPEG
+/// cannot represent (larger than `i32::MAX`) are also rejected.
 #[doc(alias = "tjBufSize")]
 pub fn compressed_buf_len(width: usize, height: usize, subsamp: Subsamp) -> Result<usize> {
-    let width = width.try_into().map_err(|_| Error::IntegerOverflow("width"))?;
-    let height = height.try_into().map_err(|_| Error::IntegerOverflow("height"))?;
-    let len = unsafe { raw::tjBufSize(width, height, subsamp as libc::c_int) };
-    let len = len.try_into().map_err(|_| Error::IntegerOverflow("buf len"))?;
+    let _: libc::c_int = width.try_into().map_err(|_| Error::IntegerOverflow("width"))?;
+    let _: libc::c_int = height.try_into().map_err(|_| Error::IntegerOverflow("height"))?;
+
+    // this allows for rare corner cases in which the JPEG image is larger than the uncompressed
+    // input, such as images with random noise
+    let (mcu_width, mcu_height) = subsamp.mcu_size();
+    let chroma_factor = match subsamp {
+        Subsamp::Gray => 0,
+        _ => 4 * 64 / (mcu_width * mcu_height),
+    };
+    let padded_width = width.div_ceil(mcu_width) * mcu_width;
+    let padded_height = height.div_ceil(mcu_height) * mcu_height;
+    let len = padded_width.checked_mul(padded_height)
+        .and_then(|len| len.checked_mul(2 + chroma_factor))
+        .and_then(|len| len.checked_add(2048))
+        .ok_or(Error::IntegerOverflow("buf len"))?;
+    // TurboJPEG returns the size as `unsigned long`, which has only 32 bits on some platforms
+    let _: libc::c_ulong = len.try_into().map_err(|_| Error::IntegerOverflow("buf len"))?;
     Ok(len)
 }
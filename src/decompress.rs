@@ -1,12 +1,23 @@
+use std::borrow::Cow;
+use std::hash::Hasher;
 use std::convert::TryInto as _;
-use crate::{Image, raw};
-use crate::common::{PixelFormat, Subsamp, Colorspace, Result, Error, get_error};
+use std::time::Instant;
+use crate::{Image, OwnedBuf, PixelSink, Transformer, YuvImage, marker, raw};
+use crate::common::{PixelFormat, Subsamp, Colorspace, Density, EntropyCoding, Upsampling, Result, Error, ErrorSeverity, get_error};
+use crate::metrics::{self, Operation};
 
 /// Decompresses JPEG data into raw pixels.
 #[derive(Debug)]
 #[doc(alias = "tjhandle")]
 pub struct Decompressor {
     handle: raw::tjhandle,
+    fill_byte: u8,
+    raw_flags: libc::c_int,
+    upsampling: Upsampling,
+    assumed_colorspace: Option<Colorspace>,
+    max_pixels: Option<usize>,
+    /// Transformer used to read the DCT coefficients, created when it is first needed.
+    transformer: Option<Transformer>,
 }
 
 unsafe impl Send for Decompressor {}
@@ -16,6 +27,7 @@ unsafe impl Send for Decompressor {}
 /// The header can be obtained without decompressing the image by calling
 /// [`Decompressor::read_header()`] or [`read_header()`][crate::read_header].
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub struct DecompressHeader {
     /// Width of the image in pixels (number of columns).
     pub width: usize,
@@ -25,6 +37,53 @@ pub struct DecompressHeader {
     pub subsamp: Subsamp,
     /// Colorspace of the compressed image.
     pub colorspace: Colorspace,
+    /// Entropy coding process of the compressed image (baseline, progressive, arithmetic or
+    /// lossless). If the SOF marker of the image cannot be parsed, this is
+    /// [`BaselineHuffman`][EntropyCoding::BaselineHuffman].
+    pub entropy_coding: EntropyCoding,
+    /// Number of color components in the compressed image: 1 for grayscale, 3 for YCbCr and RGB,
+    /// and 4 for CMYK and YCCK. If the SOF marker of the image cannot be parsed, this is derived
+    /// from the colorspace.
+    pub num_components: u8,
+}
+
+impl DecompressHeader {
+    /// Returns the width and height of the image rounded up to whole MCU blocks.
+    ///
+    /// The compressed image always consists of whole MCU blocks, whose size depends on the
+    /// [subsampling][Subsamp::mcu_size], so this is the size of the image including the partial
+    /// blocks on the right and bottom edges. Divide it by the MCU size to get the number of MCU
+    /// columns and rows, for example to plan a grid of lossless crops.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let image = turbojpeg::Image::mandelbrot(100, 75, turbojpeg::PixelFormat::RGB);
+    /// let jpeg_data = turbojpeg::compress(image.as_deref(), 90, turbojpeg::Subsamp::Sub2x2)?;
+    /// let header = turbojpeg::read_header(&jpeg_data)?;
+    /// assert_eq!(header.mcu_dimensions(), (112, 80));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn mcu_dimensions(&self) -> (usize, usize) {
+        let (mcu_width, mcu_height) = self.subsamp.mcu_size();
+        (self.width.div_ceil(mcu_width) * mcu_width, self.height.div_ceil(mcu_height) * mcu_height)
+    }
+}
+
+/// Result of a lenient decompression using [`Decompressor::decompress_lenient()`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DecodeOutcome {
+    /// Number of rows at the top of the image that contain decoded pixels. The remaining rows are
+    /// filled with the [fill byte][Decompressor::set_fill_byte].
+    ///
+    /// TurboJPEG does not report how many rows it decoded, so this is a heuristic: libjpeg-turbo
+    /// decodes missing data as neutral gray, so the rows at the bottom of a truncated image in
+    /// which every channel of every pixel is 0x80 are counted as missing. If the bottom rows of
+    /// the real image are exactly this gray, they are counted as missing as well, so
+    /// `valid_rows` may be too small and these rows are overwritten with the fill byte.
+    pub valid_rows: usize,
+    /// True if the JPEG data ended before the whole image could be decoded.
+    pub truncated: bool,
 }
 
 impl Decompressor {
@@ -34,15 +93,199 @@ impl Decompressor {
         unsafe {
             let handle = raw::tjInitDecompress();
             if !handle.is_null() {
-                Ok(Decompressor { handle })
+                Ok(Decompressor {
+                    handle,
+                    fill_byte: 0,
+                    raw_flags: 0,
+                    upsampling: Upsampling::default(),
+                    assumed_colorspace: None,
+                    max_pixels: None,
+                    transformer: None,
+                })
             } else {
                 Err(get_error(handle))
             }
         }
     }
 
+    /// Set the byte that [`decompress_lenient()`](Decompressor::decompress_lenient) uses to fill
+    /// the rows that could not be decoded.
+    ///
+    /// The default is 0.
+    pub fn set_fill_byte(&mut self, fill_byte: u8) {
+        self.fill_byte = fill_byte;
+    }
+
+    /// Set the method of upsampling the chrominance components.
+    ///
+    /// The default is [`Upsampling::Smooth`], which reduces the blockiness of the colors in images
+    /// with chrominance subsampling (such as 4:2:0) at a small cost in speed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // compress an image with 4:2:0 chrominance subsampling
+    /// let image = turbojpeg::Image::mandelbrot(160, 120, turbojpeg::PixelFormat::RGB);
+    /// let jpeg_data = turbojpeg::compress(image.as_deref(), 90, turbojpeg::Subsamp::Sub2x2)?;
+    ///
+    /// // decompress it using both upsampling methods
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let mut decompress = |upsampling| -> turbojpeg::Result<Vec<u8>> {
+    ///     decompressor.set_upsample_method(upsampling);
+    ///     let mut pixels = Vec::new();
+    ///     decompressor.decompress_to_slice(&jpeg_data, turbojpeg::PixelFormat::RGB, &mut pixels)?;
+    ///     Ok(pixels)
+    /// };
+    /// let fast = decompress(turbojpeg::Upsampling::Fast)?;
+    /// let smooth = decompress(turbojpeg::Upsampling::Smooth)?;
+    /// assert_ne!(fast, smooth);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[doc(alias = "TJFLAG_FASTUPSAMPLE")]
+    pub fn set_upsample_method(&mut self, upsampling: Upsampling) {
+        self.upsampling = upsampling;
+    }
+
+    /// Get the upsampling method set by
+    /// [`set_upsample_method()`](Decompressor::set_upsample_method).
+    pub fn upsample_method(&self) -> Upsampling {
+        self.upsampling
+    }
+
+    /// Set the colorspace that is assumed for images whose colorspace is ambiguous.
+    ///
+    /// The JPEG format does not store the colorspace of the image, so libjpeg determines it from
+    /// the JFIF and Adobe markers: images with a JFIF marker are YCbCr, and the Adobe marker tells
+    /// whether the image is RGB or YCbCr (or CMYK or YCCK for images with four components). Images
+    /// from some encoders have neither of these markers, so libjpeg guesses the colorspace from the
+    /// component IDs, and it may guess wrong, which decodes the image with swapped colors.
+    ///
+    /// This method overrides the guess for images without a JFIF or Adobe marker, both in
+    /// [`read_header()`](Decompressor::read_header) and in decompression. You can assume
+    /// [`RGB`][Colorspace::RGB] or [`YCbCr`][Colorspace::YCbCr] for images with three components
+    /// and [`CMYK`][Colorspace::CMYK] or [`YCCK`][Colorspace::YCCK] for images with four
+    /// components; the override is ignored for images with a different number of components and
+    /// for images whose colorspace is not ambiguous. `None` (the default) uses libjpeg's guess.
+    ///
+    /// The override is implemented by inserting an Adobe marker into the JPEG data, so every
+    /// [`read_header()`](Decompressor::read_header) and every decompression of an ambiguous image
+    /// copies the whole JPEG data while the override is set.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file and remove the JFIF marker
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    /// let ambiguous_data = [&jpeg_data[..2], &jpeg_data[20..]].concat();
+    ///
+    /// // libjpeg guesses YCbCr, but we can force the interpretation as RGB
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// assert_eq!(decompressor.read_header(&ambiguous_data)?.colorspace, turbojpeg::Colorspace::YCbCr);
+    /// decompressor.assume_colorspace(Some(turbojpeg::Colorspace::RGB));
+    /// assert_eq!(decompressor.read_header(&ambiguous_data)?.colorspace, turbojpeg::Colorspace::RGB);
+    ///
+    /// // the colorspace of the original image is not ambiguous
+    /// assert_eq!(decompressor.read_header(&jpeg_data)?.colorspace, turbojpeg::Colorspace::YCbCr);
+    ///
+    /// // clearing the override returns to libjpeg's guess
+    /// decompressor.assume_colorspace(None);
+    /// assert_eq!(decompressor.read_header(&ambiguous_data)?.colorspace, turbojpeg::Colorspace::YCbCr);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn assume_colorspace(&mut self, colorspace: Option<Colorspace>) {
+        self.assumed_colorspace = colorspace;
+    }
+
+    /// Returns `jpeg_data` with the colorspace set by
+    /// [`assume_colorspace()`](Decompressor::assume_colorspace), if it applies to the image.
+    fn with_assumed_colorspace<'a>(&self, jpeg_data: &'a [u8]) -> Cow<'a, [u8]> {
+        self.assumed_colorspace
+            .and_then(|colorspace| marker::insert_adobe_transform(jpeg_data, colorspace))
+            .map_or(Cow::Borrowed(jpeg_data), Cow::Owned)
+    }
+
+    /// Limit the number of pixels of the images that this decompressor decompresses.
+    ///
+    /// Decompressing an image with more than `max_pixels` pixels (width times height) fails with
+    /// [`Error::TooManyPixels`] before any memory is allocated for the image. Use this as a defense
+    /// against "decompression bombs", small JPEG files which declare a huge image in their header,
+    /// when you decompress images from untrusted sources. Reading the header with
+    /// [`read_header()`](Decompressor::read_header) is not limited. `None` (the default) disables
+    /// the limit.
+    ///
+    /// TurboJPEG 3 provides the same check as `TJPARAM_MAXPIXELS`, but this crate is built
+    /// against the TurboJPEG 2.1 API, which does not have it, so the limit is checked in Rust
+    /// after reading the header of the image.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// // the 384x256 image exceeds the limit
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// decompressor.set_max_pixels(Some(300 * 200));
+    /// let mut pixels = Vec::new();
+    /// let result = decompressor.decompress_to_slice(
+    ///     &jpeg_data, turbojpeg::PixelFormat::RGB, &mut pixels);
+    /// assert!(matches!(result, Err(turbojpeg::Error::TooManyPixels { .. })));
+    /// assert!(pixels.is_empty());
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[doc(alias = "TJPARAM_MAXPIXELS")]
+    pub fn set_max_pixels(&mut self, max_pixels: Option<usize>) {
+        self.max_pixels = max_pixels;
+    }
+
+    /// Get the limit set by [`set_max_pixels()`](Decompressor::set_max_pixels).
+    pub fn max_pixels(&self) -> Option<usize> {
+        self.max_pixels
+    }
+
+    /// Returns [`Error::TooManyPixels`] if the image exceeds the limit set by
+    /// [`set_max_pixels()`](Decompressor::set_max_pixels).
+    fn check_max_pixels(&self, header: &DecompressHeader) -> Result<()> {
+        match self.max_pixels {
+            Some(max_pixels) if header.width.saturating_mul(header.height) > max_pixels => {
+                Err(Error::TooManyPixels { width: header.width, height: header.height, max_pixels })
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Set raw TurboJPEG flags that are passed to `tjDecompress2()`.
+    ///
+    /// This is an escape hatch for the flags that this crate does not wrap, such as
+    /// [`TJFLAG_FASTDCT`][raw::TJFLAG_FASTDCT] or
+    /// [`TJFLAG_STOPONWARNING`][raw::TJFLAG_STOPONWARNING] (the `TJFLAG_*` constants are
+    /// available in the [`raw`] module). Prefer the typed setters where they exist.
+    ///
+    /// The default is 0 (no flags).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// decompressor.set_raw_flags(turbojpeg::raw::TJFLAG_FASTDCT as i32);
+    /// assert_eq!(decompressor.raw_flags(), turbojpeg::raw::TJFLAG_FASTDCT as i32);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_raw_flags(&mut self, flags: i32) {
+        self.raw_flags = flags;
+    }
+
+    /// Get the raw TurboJPEG flags set by [`set_raw_flags()`](Decompressor::set_raw_flags).
+    pub fn raw_flags(&self) -> i32 {
+        self.raw_flags
+    }
+
     /// Read the JPEG header without decompressing the image.
     ///
+    /// This also succeeds for truncated JPEG data, as long as the header itself is complete.
+    ///
     /// # Example
     ///
     /// ```
@@ -55,10 +298,13 @@ impl Decompressor {
     /// // read the JPEG header
     /// let header = decompressor.read_header(&jpeg_data)?;
     /// assert_eq!((header.width, header.height), (384, 256));
+    /// assert_eq!(header.entropy_coding, turbojpeg::EntropyCoding::ProgressiveHuffman);
+    /// assert_eq!(header.num_components, 3);
     ///
     /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
     pub fn read_header(&mut self, jpeg_data: &[u8]) -> Result<DecompressHeader> {
+        let jpeg_data = &*self.with_assumed_colorspace(jpeg_data);
         let jpeg_data_len = jpeg_data.len().try_into()
             .map_err(|_| Error::IntegerOverflow("jpeg_data.len()"))?;
         let mut width = 0;
@@ -73,15 +319,202 @@ impl Decompressor {
             )
         };
 
-        if res == 0 {
-            let width = width.try_into().map_err(|_| Error::IntegerOverflow("width"))?;
-            let height = height.try_into().map_err(|_| Error::IntegerOverflow("height"))?;
-            let subsamp = Subsamp::from_u32(subsamp as u32)?;
-            let colorspace = Colorspace::from_u32(colorspace as u32)?;
-            Ok(DecompressHeader { width, height, subsamp, colorspace })
-        } else {
-            Err(unsafe { get_error(self.handle) })
+        if res != 0 {
+            // TurboJPEG cannot read the header if the data ends before the first scan, but the
+            // header itself may be complete
+            let err = unsafe { get_error(self.handle) };
+            return match marker::parse_header(jpeg_data) {
+                Ok(header) if marker::is_truncated(jpeg_data) => Ok(header),
+                _ => Err(err),
+            }
+        }
+
+        let width = width.try_into().map_err(|_| Error::IntegerOverflow("width"))?;
+        let height = height.try_into().map_err(|_| Error::IntegerOverflow("height"))?;
+        let subsamp = Subsamp::from_u32(subsamp as u32)?;
+        let colorspace = Colorspace::from_u32(colorspace as u32)?;
+        // TurboJPEG does not report the SOF marker, so we find it ourselves; TurboJPEG has already
+        // accepted the header, so we fall back to defaults if our parser does not understand it
+        let (entropy_coding, num_components) = match marker::first_frame(jpeg_data) {
+            Ok(frame) => (frame.entropy_coding, frame.num_components as u8),
+            Err(_) => (EntropyCoding::BaselineHuffman, match colorspace {
+                Colorspace::Gray => 1,
+                Colorspace::CMYK | Colorspace::YCCK => 4,
+                _ => 3,
+            }),
+        };
+        Ok(DecompressHeader { width, height, subsamp, colorspace, entropy_coding, num_components })
+    }
+
+    /// Read only the width and height of the JPEG image.
+    ///
+    /// This is the fastest way to probe the size of an image: it reads the dimensions from the SOF
+    /// marker in pure Rust, without calling TurboJPEG, without allocating, and without determining
+    /// the subsampling or the colorspace. Use [`read_header()`](Decompressor::read_header) if you
+    /// need the full header.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// // read the dimensions
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// assert_eq!(decompressor.dimensions_only(&jpeg_data)?, (384, 256));
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn dimensions_only(&mut self, jpeg_data: &[u8]) -> Result<(usize, usize)> {
+        marker::dimensions(jpeg_data)
+    }
+
+    /// Read the pixel density from the JFIF APP0 marker.
+    ///
+    /// Returns `None` if the image does not have a JFIF marker (for example, images from many
+    /// digital cameras store the resolution only in EXIF). The density can be written using
+    /// [`Compressor::set_density()`][crate::Compressor::set_density].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// // read the density
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let density = decompressor.read_density(&jpeg_data)?;
+    /// assert_eq!(density, Some(turbojpeg::Density {
+    ///     x: 72,
+    ///     y: 72,
+    ///     unit: turbojpeg::DensityUnit::DotsPerInch,
+    /// }));
+    ///
+    /// // the density written by the compressor is read back
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// compressor.set_density(118, 118, turbojpeg::DensityUnit::DotsPerCm)?;
+    /// let image = turbojpeg::Image::mandelbrot(64, 64, turbojpeg::PixelFormat::RGB);
+    /// let jpeg_data = compressor.compress_to_vec(image.as_deref())?;
+    /// let density = decompressor.read_density(&jpeg_data)?.unwrap();
+    /// assert_eq!((density.x, density.y, density.unit), (118, 118, turbojpeg::DensityUnit::DotsPerCm));
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn read_density(&mut self, jpeg_data: &[u8]) -> Result<Option<Density>> {
+        marker::find_jfif_density(jpeg_data)
+    }
+
+    /// Read the XMP metadata from the JPEG data.
+    ///
+    /// XMP is stored in an APP1 marker, just like EXIF, but it starts with the signature
+    /// `http://ns.adobe.com/xap/1.0/`. This method returns the XMP packet (an XML document) from
+    /// the first such marker, or `None` if the image does not contain XMP. The extended XMP that
+    /// some applications split into additional APP1 markers is not included.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// // read the XMP packet
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let xmp = decompressor.read_xmp(&jpeg_data)?.expect("image contains XMP");
+    /// assert!(xmp.starts_with(b"<?xpacket begin="));
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn read_xmp(&mut self, jpeg_data: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(marker::find_xmp(jpeg_data)?.map(|xmp| xmp.to_vec()))
+    }
+
+    /// Read the quantization tables from the JPEG data.
+    ///
+    /// Returns the tables that are defined in the DQT markers before the first scan, indexed by
+    /// their slot (usually 0 for the luminance and 1 for the chrominance components; slots that
+    /// are not defined but are followed by a defined slot contain only zeros). Each table is in
+    /// the natural (row-major) order, so entry `8*v + u` is the quantization step of the DCT
+    /// coefficient with horizontal frequency `u` and vertical frequency `v`, not in the zig-zag
+    /// order in which it is stored in the marker.
+    ///
+    /// The tables are useful for analyzing how an image was compressed, for example to estimate
+    /// its quality, to detect recompression or to identify the encoder.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // at quality 50, libjpeg uses the example tables from the JPEG standard
+    /// let image = turbojpeg::Image::mandelbrot(64, 64, turbojpeg::PixelFormat::RGB);
+    /// let jpeg_data = turbojpeg::compress(image.as_deref(), 50, turbojpeg::Subsamp::Sub2x2)?;
+    ///
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let tables = decompressor.read_quant_tables(&jpeg_data)?;
+    /// assert_eq!(tables.len(), 2);
+    /// assert_eq!(&tables[0][..8], &[16, 11, 10, 16, 24, 40, 51, 61]);
+    /// assert_eq!(&tables[1][..8], &[17, 18, 24, 47, 99, 99, 99, 99]);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn read_quant_tables(&mut self, jpeg_data: &[u8]) -> Result<Vec<[u16; 64]>> {
+        marker::quant_tables(jpeg_data)
+    }
+
+    /// Determine whether a color JPEG image actually contains only shades of gray.
+    ///
+    /// Many JPEG images are stored in the YCbCr colorspace even though their content is grayscale,
+    /// so their chrominance (Cb and Cr) components are flat. This method inspects the DCT
+    /// coefficients of the chrominance components and returns true if they are all zero (up to a
+    /// small tolerance caused by rounding in the encoder). You can then decompress such images with
+    /// [`PixelFormat::GRAY`], which is faster and needs a third of the memory of RGB.
+    ///
+    /// This is much cheaper than decompressing the image, because the coefficients are read
+    /// without the inverse DCT, upsampling and color conversion. However, TurboJPEG 2.1 cannot read
+    /// the coefficients of only some MCUs, so the entropy-coded data of the whole image is still
+    /// decoded and the coefficients of the whole image are held in memory (about two bytes per
+    /// sample) during the check; the cost grows with the size of the file, not with the number of
+    /// blocks that are inspected. The inspection stops at the first block with color, so colorful
+    /// images are rejected quickly once their coefficients are decoded, but sampling only a few
+    /// blocks would not make the check any cheaper.
+    ///
+    /// Returns true for grayscale JPEG images and false for images in colorspaces other than
+    /// YCbCr (RGB, CMYK and YCCK), whose components do not separate luminance from color.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    ///
+    /// // the parrots are colorful
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    /// assert!(!decompressor.is_effectively_grayscale(&jpeg_data)?);
+    ///
+    /// // but their grayscale version compressed as an RGB image is not
+    /// let gray = turbojpeg::decompress(&jpeg_data, turbojpeg::PixelFormat::GRAY)?;
+    /// let rgb = turbojpeg::Image {
+    ///     pixels: gray.pixels.iter().flat_map(|&value| [value; 3]).collect::<Vec<u8>>(),
+    ///     width: gray.width,
+    ///     pitch: 3 * gray.width,
+    ///     height: gray.height,
+    ///     format: turbojpeg::PixelFormat::RGB,
+    /// };
+    /// let jpeg_data = turbojpeg::compress(rgb.as_deref(), 90, turbojpeg::Subsamp::Sub2x2)?;
+    /// assert!(decompressor.is_effectively_grayscale(&jpeg_data)?);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn is_effectively_grayscale(&mut self, jpeg_data: &[u8]) -> Result<bool> {
+        let header = crate::parse_header(jpeg_data)?;
+        match header.colorspace {
+            Colorspace::Gray => return Ok(true),
+            Colorspace::YCbCr => {},
+            _ => return Ok(false),
         }
+
+        let transformer = match self.transformer {
+            Some(ref mut transformer) => transformer,
+            None => self.transformer.insert(Transformer::new()?),
+        };
+        transformer.chroma_is_flat(jpeg_data, 1)
     }
 
     /// Decompress a JPEG image in `jpeg_data` into `output`.
@@ -90,6 +523,10 @@ impl Decompressor {
     /// be fully initialized by the caller. Use [`read_header()`](Decompressor::read_header) to
     /// determine the image size before calling this method.
     ///
+    /// If the JPEG data is truncated, this method returns [`Error::Truncated`]. If you want to
+    /// use the part of the image that could be decoded, please see
+    /// [`decompress_lenient()`](Decompressor::decompress_lenient).
+    ///
     /// # Example
     ///
     /// ```
@@ -118,34 +555,657 @@ impl Decompressor {
     ///
     /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
+    ///
+    /// # Pixel formats
+    ///
+    /// The pixel format of `output` does not need to match the colorspace of the JPEG image.
+    /// YCbCr, RGB and grayscale JPEG images can be decompressed into any pixel format except
+    /// [`CMYK`][PixelFormat::CMYK] (decompressing a color image into
+    /// [`GRAY`][PixelFormat::GRAY] keeps only the luminance). CMYK and YCCK JPEG images can only be
+    /// decompressed into `CMYK`, other pixel formats return an error.
+    ///
+    /// ```
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let mut pixels = Vec::new();
+    ///
+    /// // YCbCr to grayscale
+    /// decompressor.decompress_to_slice(&jpeg_data, turbojpeg::PixelFormat::GRAY, &mut pixels)?;
+    /// assert_eq!(pixels.len(), 384 * 256);
+    ///
+    /// // YCbCr to BGRA (the same pixel as in the example above, in a different order)
+    /// decompressor.decompress_to_slice(&jpeg_data, turbojpeg::PixelFormat::BGRA, &mut pixels)?;
+    /// assert_eq!(&pixels[0..4], &[89, 118, 122, 255]);
+    ///
+    /// // CMYK pixels are compressed into a YCCK JPEG, which cannot be decompressed to RGB
+    /// let cmyk_image = turbojpeg::Image::mandelbrot(64, 64, turbojpeg::PixelFormat::CMYK);
+    /// let cmyk_data = turbojpeg::compress(cmyk_image.as_deref(), 90, turbojpeg::Subsamp::None)?;
+    /// assert_eq!(decompressor.read_header(&cmyk_data)?.colorspace, turbojpeg::Colorspace::YCCK);
+    /// assert!(decompressor.decompress_to_slice(
+    ///     &cmyk_data, turbojpeg::PixelFormat::RGB, &mut pixels).is_err());
+    /// decompressor.decompress_to_slice(&cmyk_data, turbojpeg::PixelFormat::CMYK, &mut pixels)?;
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
     #[doc(alias = "tjDecompress2")]
     pub fn decompress(&mut self, jpeg_data: &[u8], output: Image<&mut [u8]>) -> Result<()> {
         output.assert_valid(output.pixels.len());
+        let start = metrics::start();
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!("decompress",
+            width = output.width, height = output.height, format = ?output.format,
+            subsamp = tracing::field::Empty, input_len = jpeg_data.len(),
+            output_len = tracing::field::Empty,
+        ).entered();
+        #[cfg(feature = "tracing")]
+        if !span.is_disabled() {
+            // TurboJPEG does not report the subsampling when decompressing, so we parse it in Rust
+            if let Ok(header) = marker::parse_header(jpeg_data) {
+                span.record("subsamp", tracing::field::debug(header.subsamp));
+            }
+        }
+
+        if self.max_pixels.is_some() {
+            let header = self.read_header(jpeg_data)?;
+            self.check_max_pixels(&header)?;
+        }
+
+        let num_pixels = output.width * output.height;
+        let output_len = num_pixels * output.format.size();
+        self.decompress_uninstrumented(jpeg_data, output)?;
+        metrics::record(start, Operation::Decompress, jpeg_data.len(), output_len, num_pixels);
+        #[cfg(feature = "tracing")]
+        span.record("output_len", output_len);
+        Ok(())
+    }
 
-        let Image { pixels, width, pitch, height, format } = output;
+    /// Decompress `jpeg_data` into `output` like [`decompress()`](Decompressor::decompress), but
+    /// without checking the maximum number of pixels and without recording metrics or a tracing
+    /// span, so that the callers that decompress an image in several steps can record them once.
+    fn decompress_uninstrumented(
+        &mut self,
+        jpeg_data: &[u8],
+        output: Image<&mut [u8]>,
+    ) -> Result<()> {
+        let Image { width, pitch, height, format, .. } = output;
         let width = width.try_into().map_err(|_| Error::IntegerOverflow("width"))?;
         let pitch = pitch.try_into().map_err(|_| Error::IntegerOverflow("pitch"))?;
         let height = height.try_into().map_err(|_| Error::IntegerOverflow("height"))?;
-        let jpeg_data_len = jpeg_data.len().try_into()
+        let decoded_data = self.with_assumed_colorspace(jpeg_data);
+        let jpeg_data_len = decoded_data.len().try_into()
             .map_err(|_| Error::IntegerOverflow("jpeg_data.len()"))?;
 
         let res = unsafe {
             raw::tjDecompress2(
                 self.handle,
-                jpeg_data.as_ptr(), jpeg_data_len,
-                pixels.as_mut_ptr(), width, pitch, height, format as i32,
-                0,
+                decoded_data.as_ptr(), jpeg_data_len,
+                output.pixels.as_mut_ptr(), width, pitch, height, format as i32,
+                self.raw_flags | self.upsampling.flags(),
             )
         };
 
         if res == 0 {
             Ok(())
         } else {
-            Err(unsafe { get_error(self.handle) })
+            let err = unsafe { get_error(self.handle) };
+            let severity = match err {
+                Error::TurboJpegError(_, severity) => severity,
+                _ => ErrorSeverity::Fatal,
+            };
+            Err(self.truncation_error(jpeg_data, &output, severity).unwrap_or(err))
+        }
+    }
+
+    /// Returns [`Error::Truncated`] if the decompression failed because `jpeg_data` is truncated,
+    /// with the `severity` of the TurboJPEG error.
+    fn truncation_error(
+        &self,
+        jpeg_data: &[u8],
+        output: &Image<&mut [u8]>,
+        severity: ErrorSeverity,
+    ) -> Option<Error> {
+        if !marker::is_truncated(jpeg_data) {
+            return None
+        }
+        let header = marker::parse_header(jpeg_data).ok()?;
+
+        // the output is only filled in if TurboJPEG merely warned about the truncation, and the
+        // decoded rows tell us how far we got only for sequential JPEGs
+        let is_sequential = header.entropy_coding == EntropyCoding::BaselineHuffman;
+        let bytes_expected_hint = if severity == ErrorSeverity::Warning && is_sequential {
+            let valid_rows = output.height - count_missing_rows(output);
+            (0 < valid_rows && valid_rows < output.height)
+                .then(|| jpeg_data.len() * output.height / valid_rows)
+        } else {
+            None
+        };
+
+        Some(Error::Truncated { bytes_available: jpeg_data.len(), bytes_expected_hint, severity })
+    }
+
+    /// Decompress a JPEG image in `jpeg_data` into a [`PixelSink`].
+    ///
+    /// This reads the JPEG header, asks `output` for the pitch and for a buffer large enough to
+    /// hold the whole image, and decompresses the image into that buffer using the given pixel
+    /// `format`. Returns the header of the image, so that you know its dimensions.
+    ///
+    /// Use this method to decompress the image directly into memory that you do not manage as an
+    /// [`Image`], such as a mapped GPU buffer (please see [`PixelSink`] for an example).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// // decompress the JPEG into a vector with tightly packed RGB pixels
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let mut pixels = Vec::new();
+    /// let header = decompressor.decompress_to_slice(
+    ///     &jpeg_data, turbojpeg::PixelFormat::RGB, &mut pixels)?;
+    /// assert_eq!(pixels.len(), 3 * header.width * header.height);
+    ///
+    /// // data that was cut off right after the header is reported as truncated
+    /// let truncated_data = &jpeg_data[..12000];
+    /// let result = decompressor.decompress_to_slice(
+    ///     truncated_data, turbojpeg::PixelFormat::RGB, &mut pixels);
+    /// assert!(matches!(result, Err(turbojpeg::Error::Truncated { bytes_available: 12000, .. })));
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn decompress_to_slice<S: PixelSink + ?Sized>(
+        &mut self,
+        jpeg_data: &[u8],
+        format: PixelFormat,
+        output: &mut S,
+    ) -> Result<DecompressHeader> {
+        let header = self.read_header(jpeg_data)?;
+        self.check_max_pixels(&header)?;
+        let pitch = output.pitch(header.width * format.size());
+        let len = pitch.checked_mul(header.height).ok_or(Error::IntegerOverflow("pitch * height"))?;
+        let image = Image {
+            pixels: output.pixel_buf(len),
+            width: header.width,
+            pitch,
+            height: header.height,
+            format,
+        };
+        self.decompress(jpeg_data, image)?;
+        Ok(header)
+    }
+
+    /// Decompress a JPEG image in `jpeg_data` into a grid of tiles.
+    ///
+    /// The image is split into tiles of `tile_width` x `tile_height` pixels, in row-major order
+    /// (from left to right and from top to bottom), and every tile is written into the image with
+    /// the same index in `tiles`. The tiles on the right and bottom edges are smaller if the size
+    /// of the image is not a multiple of the tile size; the pixels of these tiles outside of the
+    /// image are left unchanged. All tiles must use the same pixel format, which is the format of
+    /// the decompressed image. Returns the header of the image.
+    ///
+    /// The image is decompressed one row of tiles at a time: the rows are losslessly cropped from
+    /// the JPEG (all of them in a single transform) and then decompressed one after another, so
+    /// only the pixels of one row of tiles (plus one MCU row above and one below it) are held in a
+    /// temporary buffer, not the pixels of the whole image. The cropped rows are small JPEGs that
+    /// together take about as much memory as `jpeg_data`, and libjpeg holds the DCT coefficients
+    /// of the whole image while it crops them. The decoded pixels are the same as when
+    /// decompressing the whole image. Call [`read_header()`](Decompressor::read_header) first to
+    /// find out how many tiles you need.
+    ///
+    /// Panics if `tile_width` or `tile_height` is zero, if the number of `tiles` does not match the
+    /// number of tiles of the image, if a tile is smaller than `tile_width` x `tile_height` (or
+    /// than the image region on the edges), or if the tiles use different pixel formats.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// // split the 384x256 image into 3x2 tiles of 128x128 pixels
+    /// let mut tiles = (0..6)
+    ///     .map(|_| vec![0; 3 * 128 * 128])
+    ///     .map(|pixels| turbojpeg::Image::new(pixels, 128, 128, turbojpeg::PixelFormat::RGB))
+    ///     .collect::<Vec<_>>();
+    /// let mut tile_refs = tiles.iter_mut().map(|tile| tile.as_deref_mut()).collect::<Vec<_>>();
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// decompressor.decompress_tiled(&jpeg_data, 128, 128, &mut tile_refs)?;
+    ///
+    /// // the first pixel of the first tile is the first pixel of the image
+    /// assert_eq!(&tiles[0].pixels[..3], &[122, 118, 89]);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn decompress_tiled(
+        &mut self,
+        jpeg_data: &[u8],
+        tile_width: usize,
+        tile_height: usize,
+        tiles: &mut [Image<&mut [u8]>],
+    ) -> Result<DecompressHeader> {
+        assert!(tile_width > 0 && tile_height > 0, "tile size must not be zero");
+        let header = self.read_header(jpeg_data)?;
+        let (cols, rows) = (header.width.div_ceil(tile_width), header.height.div_ceil(tile_height));
+        assert_eq!(tiles.len(), cols * rows,
+            "{} tiles were given, but the {}x{} image has {}x{} tiles of size {}x{}",
+            tiles.len(), header.width, header.height, cols, rows, tile_width, tile_height);
+        let format = tiles.first().map_or(PixelFormat::RGB, |tile| tile.format);
+        assert!(tiles.iter().all(|tile| tile.format == format),
+            "tiles use different pixel formats");
+        for (idx, tile) in tiles.iter().enumerate() {
+            let (x, y) = (idx % cols * tile_width, idx / cols * tile_height);
+            let width = tile_width.min(header.width - x);
+            let height = tile_height.min(header.height - y);
+            assert!(tile.width >= width && tile.height >= height,
+                "tile {} with size {}x{} is too small for region {}x{}",
+                idx, tile.width, tile.height, width, height);
+            tile.assert_valid(tile.pixels.len());
+        }
+
+        self.decompress_bands(jpeg_data, format, tile_height, |y, band| {
+            let row_tiles = &mut tiles[y / tile_height * cols..][..cols];
+            for (col, tile) in row_tiles.iter_mut().enumerate() {
+                let x = col * tile_width;
+                let row_len = tile_width.min(header.width - x) * format.size();
+                for row in 0..band.height {
+                    let src = &band.pixels[row*band.pitch + x*format.size()..][..row_len];
+                    tile.pixels[row*tile.pitch..][..row_len].copy_from_slice(src);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Decompress `jpeg_data` in horizontal bands of `band_height` rows (the last band may be
+    /// smaller) and call `f` with the first row and the pixels of every band, from top to bottom.
+    ///
+    /// The bands are losslessly cropped from the image in a single transform and decompressed one
+    /// after another, so only the pixels of a single band are in memory at a time. Every band is
+    /// decompressed together with one MCU row above and below it, which are then discarded, so
+    /// that the upsampling of the chrominance at the edges of the band uses the same neighboring
+    /// samples as when decompressing the whole image.
+    ///
+    /// The whole call is recorded as a single decompression in the metrics and in a single
+    /// tracing span, so callers see one operation per image rather than one per band.
+    fn decompress_bands<F>(
+        &mut self,
+        jpeg_data: &[u8],
+        format: PixelFormat,
+        band_height: usize,
+        mut f: F,
+    ) -> Result<DecompressHeader>
+        where F: FnMut(usize, Image<&[u8]>) -> Result<()>
+    {
+        let mut start = metrics::start();
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!("decompress_bands",
+            format = ?format, band_height, input_len = jpeg_data.len(),
+            width = tracing::field::Empty, height = tracing::field::Empty,
+        ).entered();
+        let input_len = jpeg_data.len();
+
+        // the cropped bands must be in the assumed colorspace as well
+        let jpeg_data = self.with_assumed_colorspace(jpeg_data);
+        let jpeg_data = &*jpeg_data;
+        let header = self.read_header(jpeg_data)?;
+        self.check_max_pixels(&header)?;
+        #[cfg(feature = "tracing")]
+        span.record("width", header.width).record("height", header.height);
+        let num_pixels = header.width * header.height;
+
+        let (_, mcu_height) = header.subsamp.mcu_size();
+        let regions = (0..header.height)
+            .step_by(band_height)
+            .map(|y| {
+                let start = y.saturating_sub(mcu_height) / mcu_height * mcu_height;
+                let end = usize::min(y + band_height + mcu_height, header.height);
+                (start, end - start)
+            })
+            .collect::<Vec<_>>();
+
+        if regions.len() <= 1 {
+            let mut image = Image::new(Vec::new(), header.width, header.height, format);
+            image.pixels.resize(header.width * format.size() * header.height, 0);
+            self.decompress_uninstrumented(jpeg_data, image.as_deref_mut())?;
+            let output_len = image.pixels.len();
+            metrics::record(start, Operation::Decompress, input_len, output_len, num_pixels);
+            f(0, image.as_deref())?;
+            return Ok(header)
+        }
+
+        let transformer = match self.transformer {
+            Some(ref mut transformer) => transformer,
+            None => self.transformer.insert(Transformer::new()?),
+        };
+        let bands = transformer.crop_bands(jpeg_data, &regions)?;
+
+        let pitch = header.width * format.size();
+        let max_region_height = regions.iter().map(|&(_, height)| height).max().unwrap_or(0);
+        let len = pitch.checked_mul(max_region_height)
+            .ok_or(Error::IntegerOverflow("pitch * height"))?;
+        let mut pixels = vec![0; len];
+        for (idx, (band, &(region_y, region_height))) in bands.iter().zip(&regions).enumerate() {
+            let region = Image {
+                pixels: &mut pixels[..pitch * region_height],
+                width: header.width,
+                pitch,
+                height: region_height,
+                format,
+            };
+            self.decompress_uninstrumented(band, region)?;
+
+            let y = idx * band_height;
+            let height = band_height.min(header.height - y);
+            let callback_start = start.map(|_| Instant::now());
+            f(y, Image {
+                pixels: &pixels[(y - region_y) * pitch..][..pitch * height],
+                width: header.width,
+                pitch,
+                height,
+                format,
+            })?;
+            // the metrics measure the time spent in TurboJPEG, not in the callback
+            if let (Some(start), Some(callback_start)) = (&mut start, callback_start) {
+                *start += callback_start.elapsed();
+            }
+        }
+        let output_len = num_pixels * format.size();
+        metrics::record(start, Operation::Decompress, input_len, output_len, num_pixels);
+        Ok(header)
+    }
+
+    /// Decompress a JPEG image in `jpeg_data` and feed the decoded pixels into `hasher`.
+    ///
+    /// The image is decompressed into tightly packed pixels (so JPEGs with the same pixels hash
+    /// the same even if they are encoded differently, for example one is progressive and the
+    /// other is not): grayscale images into [`GRAY`][PixelFormat::GRAY], CMYK and YCCK images
+    /// into [`CMYK`][PixelFormat::CMYK] and all other images into [`RGB`][PixelFormat::RGB]. The
+    /// width and height of the image are hashed before the pixels, so that images with the same
+    /// bytes but different shapes hash differently.
+    ///
+    /// The image is decompressed in bands of 16 MCU rows, like in
+    /// [`decompress_tiled()`](Decompressor::decompress_tiled), so the whole decoded image is
+    /// never held in memory. The pixels of every band are passed to `hasher` in a separate call
+    /// to [`Hasher::write()`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::hash::Hasher as _;
+    ///
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let mut hash = |jpeg_data: &[u8]| -> turbojpeg::Result<u64> {
+    ///     let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    ///     decompressor.hash_pixels(jpeg_data, &mut hasher)?;
+    ///     Ok(hasher.finish())
+    /// };
+    ///
+    /// // a lossless transform from progressive to baseline coding keeps the pixels unchanged
+    /// let transform = turbojpeg::Transform { progressive: false, ..Default::default() };
+    /// let baseline_data = turbojpeg::transform(&transform, &jpeg_data)?;
+    /// assert_ne!(&baseline_data[..], &jpeg_data[..]);
+    /// assert_eq!(hash(&baseline_data)?, hash(&jpeg_data)?);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn hash_pixels<H>(&mut self, jpeg_data: &[u8], hasher: &mut H) -> Result<()>
+        where H: Hasher + ?Sized
+    {
+        let header = self.read_header(jpeg_data)?;
+        let format = match header.colorspace {
+            Colorspace::Gray => PixelFormat::GRAY,
+            Colorspace::CMYK | Colorspace::YCCK => PixelFormat::CMYK,
+            _ => PixelFormat::RGB,
+        };
+        hasher.write_usize(header.width);
+        hasher.write_usize(header.height);
+        let band_height = 16 * header.subsamp.mcu_size().1;
+        self.decompress_bands(jpeg_data, format, band_height, |_, band| {
+            hasher.write(band.pixels);
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// Decompress a JPEG image into a planar YUV image.
+    ///
+    /// This skips the color conversion and the upsampling of the chrominance components, so the
+    /// planes of the returned [`YuvImage`] contain the YCbCr samples as they are stored in the
+    /// JPEG, with the subsampling of the JPEG. Each row of each plane is padded to a multiple of
+    /// `align` bytes, which must be a power of two (use 1 for no padding). This is useful for
+    /// feeding the image into a video encoder.
+    ///
+    /// Images in the RGB, CMYK and YCCK colorspaces cannot be decompressed to YUV, so this method
+    /// returns an error for them.
+    ///
+    /// Please see [`YuvImage::plane()`] for an example.
+    #[doc(alias = "tjDecompressToYUV2")]
+    pub fn decompress_to_yuv(&mut self, jpeg_data: &[u8], align: usize) -> Result<YuvImage<Vec<u8>>> {
+        assert!(align.is_power_of_two(), "align {} is not a power of two", align);
+        let header = self.read_header(jpeg_data)?;
+        self.check_max_pixels(&header)?;
+        let start = metrics::start();
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("decompress_to_yuv",
+            width = header.width, height = header.height, input_len = jpeg_data.len(),
+        ).entered();
+
+        let mut image = YuvImage {
+            pixels: Vec::new(),
+            width: header.width,
+            align,
+            height: header.height,
+            subsamp: header.subsamp,
+        };
+        image.pixels = vec![0; image.buf_len()];
+
+        let width = header.width.try_into().map_err(|_| Error::IntegerOverflow("width"))?;
+        let height = header.height.try_into().map_err(|_| Error::IntegerOverflow("height"))?;
+        let align = align.try_into().map_err(|_| Error::IntegerOverflow("align"))?;
+        let decoded_data = self.with_assumed_colorspace(jpeg_data);
+        let jpeg_data_len = decoded_data.len().try_into()
+            .map_err(|_| Error::IntegerOverflow("jpeg_data.len()"))?;
+
+        let res = unsafe {
+            raw::tjDecompressToYUV2(
+                self.handle,
+                decoded_data.as_ptr(), jpeg_data_len,
+                image.pixels.as_mut_ptr(), width, align, height,
+                self.raw_flags,
+            )
+        };
+        if res != 0 {
+            return Err(unsafe { get_error(self.handle) })
+        }
+
+        let num_pixels = header.width * header.height;
+        metrics::record(start, Operation::Decompress, jpeg_data.len(), image.pixels.len(), num_pixels);
+        Ok(image)
+    }
+
+    /// Decompress a JPEG image into a new [`OwnedBuf`] with tightly packed RGB pixels.
+    ///
+    /// The buffer is allocated with the right size for the image, so you don't need to read the
+    /// header first. Returns the buffer together with the header of the image, so that you know
+    /// its dimensions. Use
+    /// [`decompress_to_owned_with_format()`](Decompressor::decompress_to_owned_with_format) to
+    /// choose a different pixel format.
+    ///
+    /// TurboJPEG cannot allocate buffers larger than `i32::MAX` bytes, so larger images (such as
+    /// 30000x30000 RGB pixels) fail with [`Error::IntegerOverflow`]; decompress them into a
+    /// `Vec<u8>` using [`decompress_to_vec()`](Decompressor::decompress_to_vec) instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let (pixels, header) = decompressor.decompress_to_owned(&jpeg_data)?;
+    /// assert_eq!(pixels.len(), 3 * header.width * header.height);
+    /// assert_eq!(pixels[..3], [122, 118, 89]);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn decompress_to_owned(&mut self, jpeg_data: &[u8]) -> Result<(OwnedBuf, DecompressHeader)> {
+        self.decompress_to_owned_with_format(jpeg_data, PixelFormat::RGB)
+    }
+
+    /// Decompress a JPEG image into a new [`OwnedBuf`] with tightly packed pixels in the given
+    /// `format`.
+    ///
+    /// This works like [`decompress_to_owned()`](Decompressor::decompress_to_owned), but with a
+    /// pixel format other than RGB.
+    pub fn decompress_to_owned_with_format(
+        &mut self,
+        jpeg_data: &[u8],
+        format: PixelFormat,
+    ) -> Result<(OwnedBuf, DecompressHeader)> {
+        // allocate the buffer up front, so that images too large for TurboJPEG's allocator fail
+        // with an error instead of a panic in `PixelSink::pixel_buf()`
+        let header = self.read_header(jpeg_data)?;
+        self.check_max_pixels(&header)?;
+        let len = (header.width * format.size()).checked_mul(header.height)
+            .ok_or(Error::IntegerOverflow("pitch * height"))?;
+        let mut buf = OwnedBuf::try_zeroed(len)?;
+        let header = self.decompress_to_slice(jpeg_data, format, &mut buf)?;
+        Ok((buf, header))
+    }
+
+    /// Decompress a JPEG image into a new `Vec<u8>` with tightly packed pixels in the given
+    /// `format`.
+    ///
+    /// This is the counterpart of
+    /// [`Compressor::compress_to_vec()`][crate::Compressor::compress_to_vec]: it reads the header,
+    /// allocates a vector of the right size and decompresses the image into it. Returns the
+    /// pixels together with the width and height of the image.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let (pixels, width, height) =
+    ///     decompressor.decompress_to_vec(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+    /// assert_eq!((width, height), (384, 256));
+    /// assert_eq!(pixels.len(), 3 * width * height);
+    /// assert_eq!(pixels[..3], [122, 118, 89]);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn decompress_to_vec(
+        &mut self,
+        jpeg_data: &[u8],
+        format: PixelFormat,
+    ) -> Result<(Vec<u8>, usize, usize)> {
+        let mut pixels = Vec::new();
+        let header = self.decompress_to_slice(jpeg_data, format, &mut pixels)?;
+        Ok((pixels, header.width, header.height))
+    }
+
+    /// Decompress a JPEG image in `jpeg_data` into `output`, tolerating damaged or truncated data.
+    ///
+    /// This works like [`decompress()`](Decompressor::decompress), but when TurboJPEG merely
+    /// warns about the data (for example, because the JPEG is truncated or the entropy-coded data
+    /// is corrupt), this method still returns the decoded pixels instead of an error. The returned
+    /// [`DecodeOutcome`] tells you whether the data was truncated and how many rows at the top of
+    /// the image were decoded; the rows below are filled with the [fill
+    /// byte](Decompressor::set_fill_byte).
+    ///
+    /// TurboJPEG does not report how far the decoding progressed, so the number of valid rows is
+    /// determined from the output: libjpeg-turbo decodes missing data as neutral gray, so the gray
+    /// rows at the bottom of a truncated image are considered invalid. For progressive JPEGs, the
+    /// first scans usually cover the whole image, so all rows are reported as valid (but the
+    /// image may be blurry).
+    ///
+    /// If the entropy-coded data is corrupt in the middle of an image that contains restart
+    /// markers, libjpeg skips to the next restart marker and continues decoding from there, so
+    /// only the band of MCUs between the two markers is damaged and the rest of the image is
+    /// decoded correctly. TurboJPEG does not report which bands were damaged, so they are not
+    /// reflected in the returned [`DecodeOutcome`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file and simulate an incomplete download
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    /// let jpeg_data = &jpeg_data[..jpeg_data.len() / 2];
+    ///
+    /// // the header is still intact
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let header = decompressor.read_header(jpeg_data)?;
+    ///
+    /// // decompress whatever we can
+    /// let mut image = turbojpeg::Image {
+    ///     pixels: vec![0; 3 * header.width * header.height],
+    ///     width: header.width,
+    ///     pitch: 3 * header.width,
+    ///     height: header.height,
+    ///     format: turbojpeg::PixelFormat::RGB,
+    /// };
+    /// let outcome = decompressor.decompress_lenient(jpeg_data, image.as_deref_mut())?;
+    /// assert!(outcome.truncated);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn decompress_lenient(
+        &mut self,
+        jpeg_data: &[u8],
+        mut output: Image<&mut [u8]>,
+    ) -> Result<DecodeOutcome> {
+        let err = match self.decompress(jpeg_data, output.as_deref_mut()) {
+            Ok(()) => return Ok(DecodeOutcome { valid_rows: output.height, truncated: false }),
+            Err(err) => err,
+        };
+
+        // only TurboJPEG warnings leave decoded pixels in the output, errors detected on the Rust
+        // side (such as `TooManyPixels`) happen before anything is decoded
+        let severity = match err {
+            Error::TurboJpegError(_, severity) | Error::Truncated { severity, .. } => severity,
+            _ => return Err(err),
+        };
+        if severity != ErrorSeverity::Warning {
+            return Err(err)
+        }
+
+        let truncated = matches!(err, Error::Truncated { .. });
+        let valid_rows = if truncated {
+            output.height - count_missing_rows(&output)
+        } else {
+            output.height
+        };
+
+        let row_len = output.width * output.format.size();
+        for y in valid_rows..output.height {
+            output.pixels[y*output.pitch..][..row_len].fill(self.fill_byte);
         }
+
+        Ok(DecodeOutcome { valid_rows, truncated })
     }
 }
 
+/// Counts the rows at the bottom of the image that libjpeg-turbo filled in for missing data.
+///
+/// libjpeg-turbo decodes missing entropy-coded data as zero DCT coefficients, which produces pixels
+/// with all color components equal to 128.
+fn count_missing_rows(image: &Image<&mut [u8]>) -> usize {
+    let padding = match image.format {
+        PixelFormat::RGBX | PixelFormat::BGRX | PixelFormat::RGBA | PixelFormat::BGRA => Some(3),
+        PixelFormat::XBGR | PixelFormat::XRGB | PixelFormat::ABGR | PixelFormat::ARGB => Some(0),
+        _ => None,
+    };
+    let pixel_size = image.format.size();
+    let row_len = image.width * pixel_size;
+    (0..image.height).rev()
+        .take_while(|&y| {
+            let row = &image.pixels[y*image.pitch..][..row_len];
+            row.chunks_exact(pixel_size).all(|pixel| {
+                pixel.iter().enumerate().all(|(i, &value)| Some(i) == padding || value == 0x80)
+            })
+        })
+        .count()
+}
+
 impl Drop for Decompressor {
     fn drop(&mut self) {
         unsafe { raw::tjDestroy(self.handle); }
@@ -188,6 +1248,39 @@ pub fn decompress(jpeg_data: &[u8], format: PixelFormat) -> Result<Image<Vec<u8>
     Ok(image)
 }
 
+/// Decompress a JPEG image into a grayscale image with one byte per pixel.
+///
+/// This is a shorthand for [`decompress()`] with [`PixelFormat::GRAY`] and the counterpart of
+/// [`compress_gray()`][crate::compress_gray]. Color images are converted to grayscale, which only
+/// decodes their luminance.
+///
+/// # Example
+///
+/// ```
+/// // read JPEG data from file
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+///
+/// // decompress the JPEG into a grayscale image
+/// let image = turbojpeg::decompress_gray(&jpeg_data)?;
+/// assert_eq!((image.width, image.height), (384, 256));
+/// assert_eq!(image.pixels.len(), 384 * 256);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn decompress_gray(jpeg_data: &[u8]) -> Result<Image<Vec<u8>>> {
+    decompress(jpeg_data, PixelFormat::GRAY)
+}
+
+/// Decompress a JPEG image into tightly packed pixels with the given `format`.
+///
+/// Returns the pixels and the header of the image, which tells you the size of the image. This is
+/// the counterpart of [`encode()`][crate::encode], please see it for an example.
+pub fn decode(jpeg_data: &[u8], format: PixelFormat) -> Result<(Vec<u8>, DecompressHeader)> {
+    let mut pixels = Vec::new();
+    let header = Decompressor::new()?.decompress_to_slice(jpeg_data, format, &mut pixels)?;
+    Ok((pixels, header))
+}
+
 /// Read the JPEG header without decompressing the image.
 ///
 /// # Example
@@ -206,3 +1299,40 @@ pub fn read_header(jpeg_data: &[u8]) -> Result<DecompressHeader> {
     let mut decompressor = Decompressor::new()?;
     decompressor.read_header(jpeg_data)
 }
+
+/// Read the JPEG headers of many images without decompressing them.
+///
+/// This is the same as calling [`read_header()`] for every image in `inputs`, but a single
+/// [`Decompressor`] is reused for all images, so it is faster when you need to scan many images.
+/// The returned iterator is lazy and yields one result for every input.
+///
+/// # Example
+///
+/// ```
+/// // read JPEG data from files
+/// let files = vec![
+///     std::fs::read("examples/parrots.jpg")?,
+///     std::fs::read("examples/parrots.jpg")?,
+/// ];
+///
+/// // read all headers using a single decompressor
+/// for header in turbojpeg::scan_headers(files.iter().map(|data| &data[..])) {
+///     let header = header?;
+///     assert_eq!((header.width, header.height), (384, 256));
+///     assert_eq!(header.subsamp, turbojpeg::Subsamp::None);
+/// }
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn scan_headers<'a, I>(inputs: I) -> impl Iterator<Item = Result<DecompressHeader>> + 'a
+    where I: IntoIterator<Item = &'a [u8]>, I::IntoIter: 'a
+{
+    // the decompressor is created lazily, so that we don't allocate it for empty inputs
+    let mut decompressor = None;
+    inputs.into_iter().map(move |jpeg_data| {
+        if decompressor.is_none() {
+            decompressor = Some(Decompressor::new()?);
+        }
+        decompressor.as_mut().unwrap().read_header(jpeg_data)
+    })
+}
@@ -0,0 +1,119 @@
+use crate::{Channel, Decompressor, DecompressHeader, PixelFormat};
+use crate::common::Result;
+
+/// Memory layout of the `f32` pixels produced by [`Decompressor::decompress_to_f32()`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Layout {
+    /// Height, width, channels: the channels of each pixel are stored next to each other (this is
+    /// the layout of the `u8` pixels in an [`Image`][crate::Image]).
+    Hwc,
+    /// Channels, height, width: each channel is stored in a separate plane (this is the layout
+    /// expected by most PyTorch models).
+    Chw,
+}
+
+/// Normalization of the `f32` pixels produced by [`Decompressor::decompress_to_f32()`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Normalization {
+    /// Pixel values are scaled to the range `0.0..=1.0`.
+    Unit,
+    /// Pixel values are scaled to the range `0.0..=1.0`, and then the `mean` is subtracted and the
+    /// result is divided by `std`, separately for each channel. The elements are always given in
+    /// the order red, green, blue, regardless of the order of the channels in the output (so they
+    /// are applied in reverse order for [`BGR`][PixelFormat::BGR] output). For grayscale images,
+    /// only the first element is used.
+    ///
+    /// For example, models trained on ImageNet commonly use mean `[0.485, 0.456, 0.406]` and std
+    /// `[0.229, 0.224, 0.225]` for RGB images.
+    MeanStd {
+        /// Mean of each channel.
+        mean: [f32; 3],
+        /// Standard deviation of each channel.
+        std: [f32; 3],
+    },
+}
+
+impl Decompressor {
+    /// Decompress a JPEG image into normalized `f32` pixels.
+    ///
+    /// The image is decompressed into `u8` pixels with the given `format`, which must be
+    /// [`RGB`][PixelFormat::RGB], [`BGR`][PixelFormat::BGR] or [`GRAY`][PixelFormat::GRAY], and
+    /// the pixels are then converted to `f32` using the `normalization` and stored in `output`
+    /// using the `layout`. Returns the header of the image, so that you know its dimensions.
+    ///
+    /// Panics if the pixel format is not supported or if `output` is too small: it must have at
+    /// least `width * height * format.size()` elements (use
+    /// [`read_header()`](Decompressor::read_header) to determine the image size).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// // decompress the image into a CHW tensor normalized with the ImageNet statistics
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let mut tensor = vec![0.0; 3 * 384 * 256];
+    /// decompressor.decompress_to_f32(
+    ///     &jpeg_data,
+    ///     &mut tensor,
+    ///     turbojpeg::PixelFormat::RGB,
+    ///     turbojpeg::Layout::Chw,
+    ///     turbojpeg::Normalization::MeanStd {
+    ///         mean: [0.485, 0.456, 0.406],
+    ///         std: [0.229, 0.224, 0.225],
+    ///     },
+    /// )?;
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn decompress_to_f32(
+        &mut self,
+        jpeg_data: &[u8],
+        output: &mut [f32],
+        format: PixelFormat,
+        layout: Layout,
+        normalization: Normalization,
+    ) -> Result<DecompressHeader> {
+        assert!(matches!(format, PixelFormat::RGB | PixelFormat::BGR | PixelFormat::GRAY),
+            "decompress_to_f32() does not support pixel format {:?}", format);
+
+        let mut pixels = Vec::new();
+        let header = self.decompress_to_slice(jpeg_data, format, &mut pixels)?;
+        assert!(output.len() >= pixels.len(),
+            "output length {} is too small for width {}, height {} and pixel format {:?}",
+            output.len(), header.width, header.height, format);
+
+        let channels = format.size();
+        let (mean, std) = match normalization {
+            Normalization::Unit => ([0.; 3], [1.; 3]),
+            Normalization::MeanStd { mean, std } => (mean, std),
+        };
+        // precompute the mapping from u8 values to f32 for every byte of the pixel, using the
+        // normalization of the channel that the byte stores
+        let mut lut = [[0f32; 256]; 3];
+        for (lut, &channel) in lut.iter_mut().zip(format.channel_order()) {
+            let c = match channel {
+                Channel::G => 1,
+                Channel::B => 2,
+                _ => 0,
+            };
+            for (value, out) in lut.iter_mut().enumerate() {
+                *out = (value as f32 / 255. - mean[c]) / std[c];
+            }
+        }
+
+        let plane_len = header.width * header.height;
+        for (i, pixel) in pixels.chunks_exact(channels).enumerate() {
+            for (c, &value) in pixel.iter().enumerate() {
+                let index = match layout {
+                    Layout::Hwc => i * channels + c,
+                    Layout::Chw => c * plane_len + i,
+                };
+                output[index] = lut[c][value as usize];
+            }
+        }
+
+        Ok(header)
+    }
+}
@@ -1,5 +1,6 @@
 use std::ops::{Deref, DerefMut};
-use crate::common::PixelFormat;
+use crate::buf::OwnedBuf;
+use crate::common::{Channel, Error, PixelFormat, Result};
 
 /// An image with pixels of type `T`.
 ///
@@ -14,6 +15,10 @@ use crate::common::PixelFormat;
 ///
 /// Data for pixel in column `x` and row `y` is stored in `pixels` at offset `y*pitch +
 /// x*format.size()`.
+///
+/// Prefer creating images using [`Image::new()`], which computes the pitch from the width and the
+/// pixel format, so that you cannot get it wrong (a common mistake is to use `3 * width` for a
+/// grayscale image). Use [`Image::with_pitch()`] if the rows of your image are padded.
 #[derive(Debug, Copy, Clone)]
 pub struct Image<T> {
     /// Pixel data of the image (typically `&[u8]`, `&mut [u8]` or `Vec<u8>`).
@@ -31,6 +36,45 @@ pub struct Image<T> {
 }
 
 impl<T> Image<T> {
+    /// Creates an image with rows that are not padded, so the pitch is `width * format.size()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let pixels = vec![0; 4 * 3];
+    /// let image = turbojpeg::Image::new(&pixels[..], 4, 3, turbojpeg::PixelFormat::GRAY);
+    /// assert_eq!(image.pitch, 4);
+    /// ```
+    pub fn new(pixels: T, width: usize, height: usize, format: PixelFormat) -> Image<T> {
+        Image { pixels, width, pitch: width * format.size(), height, format }
+    }
+
+    /// Creates an image with the given `pitch`, which is the size of one row in bytes.
+    ///
+    /// Use this for images with padding at the end of each row.
+    ///
+    /// Panics if `pitch` is smaller than `width * format.size()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // rows of 3 RGB pixels padded to 16 bytes
+    /// let pixels = vec![0; 16 * 2];
+    /// let image = turbojpeg::Image::with_pitch(&pixels[..], 3, 16, 2, turbojpeg::PixelFormat::RGB);
+    /// assert_eq!(image.tight_bytes().len(), 3 * 3 * 2);
+    /// ```
+    pub fn with_pitch(
+        pixels: T,
+        width: usize,
+        pitch: usize,
+        height: usize,
+        format: PixelFormat,
+    ) -> Image<T> {
+        assert!(pitch >= width*format.size(),
+            "pitch {} is too small for width {} and pixel format {:?}", pitch, width, format);
+        Image { pixels, width, pitch, height, format }
+    }
+
     /// Converts from `&Image<T>` to `Image<&T::Target>`.
     ///
     /// In particular, you can use this to get `Image<&[u8]>` from `Image<Vec<u8>>`.
@@ -67,6 +111,178 @@ impl<T> Image<T> {
     }
 }
 
+impl<T: Deref<Target = [u8]>> Image<T> {
+    /// Copies the pixels into a new `Vec` without the padding at the end of each row.
+    ///
+    /// The returned data is tightly packed, so it contains exactly `width * format.size()` bytes
+    /// for each of the `height` rows. This is useful when you pass the pixels to code that does not
+    /// support a pitch.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // an image with 2x2 RGB pixels, where each row is padded to 8 bytes
+    /// let image = turbojpeg::Image {
+    ///     pixels: vec![1, 2, 3, 4, 5, 6, 0, 0, 7, 8, 9, 10, 11, 12, 0, 0],
+    ///     width: 2,
+    ///     pitch: 8,
+    ///     height: 2,
+    ///     format: turbojpeg::PixelFormat::RGB,
+    /// };
+    /// assert_eq!(image.tight_bytes(), vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+    /// ```
+    pub fn tight_bytes(&self) -> Vec<u8> {
+        self.assert_valid(self.pixels.len());
+        let row_len = self.width * self.format.size();
+        if self.pitch == row_len {
+            return self.pixels[..row_len * self.height].to_vec()
+        }
+
+        let mut pixels = Vec::with_capacity(row_len * self.height);
+        for y in 0..self.height {
+            pixels.extend_from_slice(&self.pixels[y*self.pitch..][..row_len]);
+        }
+        pixels
+    }
+
+    /// Returns an iterator over the rows of the image.
+    ///
+    /// Every row contains exactly `width * format.size()` bytes, without the padding at the end of
+    /// the row.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // an image with 2x2 RGB pixels, where each row is padded to 8 bytes
+    /// let pixels = vec![1, 2, 3, 4, 5, 6, 0, 0, 7, 8, 9, 10, 11, 12, 0, 0];
+    /// let image = turbojpeg::Image::with_pitch(pixels, 2, 8, 2, turbojpeg::PixelFormat::RGB);
+    /// let rows = image.rows().collect::<Vec<_>>();
+    /// assert_eq!(rows, vec![&[1, 2, 3, 4, 5, 6], &[7, 8, 9, 10, 11, 12]]);
+    ///
+    /// // an image with zero width still has `height` (empty) rows
+    /// let empty = turbojpeg::Image::new(&[][..], 0, 3, turbojpeg::PixelFormat::RGB);
+    /// assert_eq!(empty.rows().len(), 3);
+    /// ```
+    pub fn rows(&self) -> impl ExactSizeIterator<Item = &[u8]> {
+        self.assert_valid(self.pixels.len());
+        let (row_len, pitch) = (self.width * self.format.size(), self.pitch);
+        let pixels = &self.pixels[..];
+        (0..self.height).map(move |y| &pixels[y*pitch..][..row_len])
+    }
+
+    /// Drops the alpha (or padding) channel, producing a tightly packed RGB or BGR image.
+    ///
+    /// JPEG does not store alpha, so compressing an RGB image is cheaper than compressing an
+    /// RGBA image, which TurboJPEG has to read four bytes per pixel from. The order of the color
+    /// channels is preserved: [`RGBA`][PixelFormat::RGBA], [`RGBX`][PixelFormat::RGBX],
+    /// [`ARGB`][PixelFormat::ARGB] and [`XRGB`][PixelFormat::XRGB] images are converted to
+    /// [`RGB`][PixelFormat::RGB], and the BGR variants are converted to [`BGR`][PixelFormat::BGR].
+    ///
+    /// Panics if the image has a pixel format without an alpha or padding channel.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let image = turbojpeg::Image::mandelbrot(100, 50, turbojpeg::PixelFormat::RGBA);
+    /// let rgb = image.rgba_to_rgb();
+    /// assert_eq!(rgb.format, turbojpeg::PixelFormat::RGB);
+    /// assert_eq!(rgb.pitch, 3 * 100);
+    /// assert_eq!(&rgb.pixels[..3], &image.pixels[..3]);
+    /// ```
+    pub fn rgba_to_rgb(&self) -> Image<Vec<u8>> {
+        let (format, alpha) = match self.format {
+            PixelFormat::RGBA | PixelFormat::RGBX => (PixelFormat::RGB, 3),
+            PixelFormat::BGRA | PixelFormat::BGRX => (PixelFormat::BGR, 3),
+            PixelFormat::ARGB | PixelFormat::XRGB => (PixelFormat::RGB, 0),
+            PixelFormat::ABGR | PixelFormat::XBGR => (PixelFormat::BGR, 0),
+            other => panic!("rgba_to_rgb() does not support pixel format {:?}", other),
+        };
+        self.assert_valid(self.pixels.len());
+
+        let mut pixels = Vec::with_capacity(3 * self.width * self.height);
+        for y in 0..self.height {
+            let row = &self.pixels[y*self.pitch..][..4*self.width];
+            for pixel in row.chunks_exact(4) {
+                pixels.extend(pixel.iter().enumerate().filter(|&(i, _)| i != alpha).map(|(_, &v)| v));
+            }
+        }
+
+        Image { pixels, width: self.width, pitch: 3 * self.width, height: self.height, format }
+    }
+
+    /// Adds an opaque alpha channel, producing a tightly packed RGBA or BGRA image.
+    ///
+    /// This is the inverse of [`rgba_to_rgb()`][Image::rgba_to_rgb]: [`RGB`][PixelFormat::RGB]
+    /// images are converted to [`RGBA`][PixelFormat::RGBA] and [`BGR`][PixelFormat::BGR] images
+    /// are converted to [`BGRA`][PixelFormat::BGRA], with alpha set to 255.
+    ///
+    /// Panics if the image has a pixel format other than RGB or BGR.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let image = turbojpeg::Image::mandelbrot(100, 50, turbojpeg::PixelFormat::RGB);
+    /// let rgba = image.rgb_to_rgba();
+    /// assert_eq!(rgba.format, turbojpeg::PixelFormat::RGBA);
+    /// assert_eq!(rgba.pitch, 4 * 100);
+    /// assert_eq!(&rgba.pixels[..4], &[image.pixels[0], image.pixels[1], image.pixels[2], 255]);
+    /// ```
+    pub fn rgb_to_rgba(&self) -> Image<Vec<u8>> {
+        let format = match self.format {
+            PixelFormat::RGB => PixelFormat::RGBA,
+            PixelFormat::BGR => PixelFormat::BGRA,
+            other => panic!("rgb_to_rgba() does not support pixel format {:?}", other),
+        };
+        self.assert_valid(self.pixels.len());
+
+        let mut pixels = Vec::with_capacity(4 * self.width * self.height);
+        for y in 0..self.height {
+            let row = &self.pixels[y*self.pitch..][..3*self.width];
+            for pixel in row.chunks_exact(3) {
+                pixels.extend_from_slice(pixel);
+                pixels.push(255);
+            }
+        }
+
+        Image { pixels, width: self.width, pitch: 4 * self.width, height: self.height, format }
+    }
+}
+
+impl<T: DerefMut<Target = [u8]>> Image<T> {
+    /// Returns an iterator over the mutable rows of the image.
+    ///
+    /// Every row contains exactly `width * format.size()` bytes, without the padding at the end of
+    /// the row, so you cannot accidentally overwrite the padding.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file and decompress it
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    /// let mut image = turbojpeg::decompress(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+    ///
+    /// // draw a black line across every tenth row
+    /// for row in image.rows_mut().step_by(10) {
+    ///     row.fill(0);
+    /// }
+    /// assert_eq!(&image.pixels[..3], &[0, 0, 0]);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn rows_mut(&mut self) -> impl ExactSizeIterator<Item = &mut [u8]> {
+        self.assert_valid(self.pixels.len());
+        let (row_len, pitch) = (self.width * self.format.size(), self.pitch);
+        let mut rest = &mut self.pixels[..];
+        (0..self.height).map(move |_| {
+            // the last row may not be followed by padding
+            let rest_len = rest.len();
+            let (row, tail) = std::mem::take(&mut rest).split_at_mut(pitch.min(rest_len));
+            rest = tail;
+            &mut row[..row_len]
+        })
+    }
+}
+
 impl Image<Vec<u8>> {
     /// Generates an image of the Mandelbrot set.
     ///
@@ -167,3 +383,69 @@ impl Image<Vec<u8>> {
     }
 }
 
+
+/// Converts the pixels of `src` into another pixel format, without going through JPEG.
+///
+/// Returns the tightly packed pixels in `dst_format`, so the pitch of the result is `src.width *
+/// dst_format.size()`. The color channels are reordered as needed; the alpha channel is copied
+/// from the alpha (or padding) channel of `src`, or set to 0xFF if `src` does not have one.
+/// Grayscale pixels can be converted into any RGB format, by copying the luminance into every
+/// color channel.
+///
+/// All other conversions (such as from RGB to grayscale, or between RGB and CMYK) would change the
+/// colors, not just their layout, so they return [`Error::UnsupportedConversion`].
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::PixelFormat;
+///
+/// // two BGRA pixels
+/// let pixels = [3, 2, 1, 255, 6, 5, 4, 128];
+/// let image = turbojpeg::Image::new(&pixels[..], 2, 1, PixelFormat::BGRA);
+///
+/// let rgb = turbojpeg::convert_pixels(image, PixelFormat::RGB)?;
+/// assert_eq!(&rgb[..], &[1, 2, 3, 4, 5, 6]);
+/// let argb = turbojpeg::convert_pixels(image, PixelFormat::ARGB)?;
+/// assert_eq!(&argb[..], &[255, 1, 2, 3, 128, 4, 5, 6]);
+///
+/// // converting colors to grayscale is not a simple reordering of bytes
+/// assert!(turbojpeg::convert_pixels(image, PixelFormat::GRAY).is_err());
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn convert_pixels(src: Image<&[u8]>, dst_format: PixelFormat) -> Result<OwnedBuf> {
+    src.assert_valid(src.pixels.len());
+    let src_order = src.format.channel_order();
+    let find = |channel| src_order.iter().position(|&c| c == channel);
+
+    // for every byte of the destination pixel, the index of the byte in the source pixel that it
+    // is copied from, or `None` if it is set to 0xFF
+    let mapping = dst_format.channel_order()
+        .iter()
+        .map(|&channel| match channel {
+            Channel::A | Channel::X => Ok(find(Channel::A).or_else(|| find(Channel::X))),
+            Channel::R | Channel::G | Channel::B if src.format == PixelFormat::GRAY => Ok(Some(0)),
+            channel => find(channel)
+                .map(Some)
+                .ok_or(Error::UnsupportedConversion(src.format, dst_format)),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let (src_size, dst_size) = (src.format.size(), dst_format.size());
+    let dst_row_len = src.width * dst_size;
+    let len = dst_row_len.checked_mul(src.height)
+        .ok_or(Error::IntegerOverflow("width * height"))?;
+    let mut buf = OwnedBuf::try_zeroed(len)?;
+    for y in 0..src.height {
+        let src_row = &src.pixels[y*src.pitch..][..src.width*src_size];
+        let dst_row = &mut buf[y*dst_row_len..][..dst_row_len];
+        let dst_pixels = dst_row.chunks_exact_mut(dst_size);
+        for (src_pixel, dst_pixel) in src_row.chunks_exact(src_size).zip(dst_pixels) {
+            for (dst_byte, &src_idx) in dst_pixel.iter_mut().zip(&mapping) {
+                *dst_byte = src_idx.map_or(0xff, |src_idx| src_pixel[src_idx]);
+            }
+        }
+    }
+    Ok(buf)
+}
@@ -1,7 +1,7 @@
-use crate::Image;
+use crate::{Image, raw};
 use crate::buf::OwnedBuf;
 use crate::compress::Compressor;
-use crate::common::{PixelFormat, Result, Subsamp};
+use crate::common::{Error, PixelFormat, Result, Subsamp};
 use crate::decompress::Decompressor;
 
 /// Decompresses image from JPEG into an [`image::ImageBuffer`].
@@ -21,7 +21,16 @@ use crate::decompress::Decompressor;
 pub fn decompress_image<P>(jpeg_data: &[u8]) -> Result<image::ImageBuffer<P, Vec<u8>>>
     where P: JpegPixel + 'static
 {
-    let mut decompressor = Decompressor::new()?;
+    decompress_image_with(&mut Decompressor::new()?, jpeg_data)
+}
+
+/// Decompresses image from JPEG into an [`image::ImageBuffer`] using the given `decompressor`.
+pub(crate) fn decompress_image_with<P>(
+    decompressor: &mut Decompressor,
+    jpeg_data: &[u8],
+) -> Result<image::ImageBuffer<P, Vec<u8>>>
+    where P: JpegPixel + 'static
+{
     let header = decompressor.read_header(jpeg_data)?;
 
     let pitch = header.width * P::PIXEL_FORMAT.size();
@@ -43,6 +52,150 @@ pub fn decompress_image<P>(jpeg_data: &[u8]) -> Result<image::ImageBuffer<P, Vec
     Ok(image_buf)
 }
 
+/// Decompresses a JPEG into an [`image::RgbImage`] thumbnail that fits into `max_width` x
+/// `max_height`.
+///
+/// The thumbnail preserves the aspect ratio of the image, and it is never larger than the
+/// image. TurboJPEG can scale the image already in the DCT domain (by factors such as 1/2, 1/4 or
+/// 1/8), which is much faster than decoding the full image, so this function decodes the image at
+/// the smallest scale that is still at least as large as the thumbnail. If that does not produce
+/// the exact thumbnail size, the decoded image is then resized using
+/// [`image::imageops::resize()`] with the Catmull-Rom filter.
+///
+/// Panics if `max_width` or `max_height` is zero.
+///
+/// # Example
+///
+/// ```
+/// // read JPEG data from file
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+///
+/// // the 384x256 image is decoded at scale 3/8 (144x96) and then resized to 120x80
+/// let thumbnail = turbojpeg::thumbnail_image(&jpeg_data, 120, 120)?;
+/// assert_eq!(thumbnail.dimensions(), (120, 80));
+///
+/// // the 384x256 image is decoded at scale 1/4 without any resizing
+/// let thumbnail = turbojpeg::thumbnail_image(&jpeg_data, 100, 64)?;
+/// assert_eq!(thumbnail.dimensions(), (96, 64));
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+pub fn thumbnail_image(jpeg_data: &[u8], max_width: u32, max_height: u32) -> Result<image::RgbImage> {
+    assert!(max_width > 0 && max_height > 0, "thumbnail size must not be zero");
+    let mut decompressor = Decompressor::new()?;
+    let header = decompressor.read_header(jpeg_data)?;
+    let (width, height) = (header.width as f64, header.height as f64);
+
+    // size of the thumbnail that fits into the box, never larger than the image
+    let scale = f64::min(1., f64::min(max_width as f64 / width, max_height as f64 / height));
+    let thumb_width = ((width * scale).round() as usize).max(1);
+    let thumb_height = ((height * scale).round() as usize).max(1);
+
+    // the smallest DCT scaling that still produces at least the size of the thumbnail
+    let (scaled_width, scaled_height) = scaled_sizes(header.width, header.height)
+        .into_iter()
+        .filter(|&(w, h)| w >= thumb_width && h >= thumb_height)
+        .min()
+        .unwrap_or((header.width, header.height));
+
+    let pitch = 3 * scaled_width;
+    let mut image_data = vec![0; pitch * scaled_height];
+    let image = Image {
+        pixels: &mut image_data[..],
+        width: scaled_width,
+        pitch,
+        height: scaled_height,
+        format: PixelFormat::RGB,
+    };
+    decompressor.decompress(jpeg_data, image)?;
+    let image_buf = image::RgbImage::from_raw(
+        scaled_width as u32,
+        scaled_height as u32,
+        image_data,
+    ).unwrap();
+
+    if (scaled_width, scaled_height) == (thumb_width, thumb_height) {
+        return Ok(image_buf)
+    }
+    Ok(image::imageops::resize(
+        &image_buf,
+        thumb_width as u32,
+        thumb_height as u32,
+        image::imageops::FilterType::CatmullRom,
+    ))
+}
+
+/// Decompresses only the luminance of a JPEG into a downscaled [`image::GrayImage`] preview.
+///
+/// The image is decoded directly into grayscale at the largest DCT scaling factor (such as 1/2,
+/// 1/4 or 1/8) for which neither dimension of the preview exceeds `max_dim`. If even the smallest
+/// scaling factor produces a larger image, it is used anyway, so the preview may be larger than
+/// `max_dim` for very large images. The image is never upscaled and there is no resizing after
+/// the decoding, so this is much faster than decoding the full RGB image, resizing it and
+/// converting it to grayscale. This is useful for perceptual hashing or deduplication, which
+/// need only a small grayscale version of the image.
+///
+/// Panics if `max_dim` is zero.
+///
+/// # Example
+///
+/// ```
+/// // read JPEG data from file
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+///
+/// // the 384x256 image is decoded at scale 1/4
+/// let preview = turbojpeg::decompress_luma_preview(&jpeg_data, 100)?;
+/// assert_eq!(preview.dimensions(), (96, 64));
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+pub fn decompress_luma_preview(jpeg_data: &[u8], max_dim: u32) -> Result<image::GrayImage> {
+    assert!(max_dim > 0, "preview size must not be zero");
+    let mut decompressor = Decompressor::new()?;
+    let header = decompressor.read_header(jpeg_data)?;
+
+    let scaled_sizes = scaled_sizes(header.width, header.height);
+    let (width, height) = scaled_sizes.iter().copied()
+        .filter(|&(w, h)| w.max(h) <= max_dim as usize)
+        .max()
+        .or_else(|| scaled_sizes.iter().copied().min())
+        .unwrap_or((header.width, header.height));
+
+    let mut image_data = vec![0; width * height];
+    let image = Image {
+        pixels: &mut image_data[..],
+        width,
+        pitch: width,
+        height,
+        format: PixelFormat::GRAY,
+    };
+    decompressor.decompress(jpeg_data, image)?;
+    let image_buf = image::GrayImage::from_raw(width as u32, height as u32, image_data).unwrap();
+    Ok(image_buf)
+}
+
+/// Returns the sizes (width, height) to which TurboJPEG can downscale an image while decoding.
+fn scaled_sizes(width: usize, height: usize) -> Vec<(usize, usize)> {
+    scaling_factors()
+        .into_iter()
+        .filter(|&(num, denom)| num <= denom)
+        .map(|(num, denom)| ((width * num).div_ceil(denom), (height * num).div_ceil(denom)))
+        .collect()
+}
+
+/// Returns the scaling factors (numerator, denominator) supported by TurboJPEG.
+fn scaling_factors() -> Vec<(usize, usize)> {
+    let mut len = 0;
+    let factors = unsafe { raw::tjGetScalingFactors(&mut len) };
+    if factors.is_null() {
+        return Vec::new()
+    }
+    let factors = unsafe { std::slice::from_raw_parts(factors, len as usize) };
+    factors.iter().map(|factor| (factor.num as usize, factor.denom as usize)).collect()
+}
+
 /// Compresses an [`image::ImageBuffer`] into JPEG.
 ///
 /// `quality` controls the tradeoff between image quality and size of the compressed image. It
@@ -52,6 +205,11 @@ pub fn decompress_image<P>(jpeg_data: &[u8]) -> Result<image::ImageBuffer<P, Vec
 /// the documentation of [`Subsamp`] for details). Use [`Subsamp::None`] for no subsampling
 /// (highest quality).
 ///
+/// The pixels of `image_buf` are passed to TurboJPEG directly, without any intermediate copy. In
+/// particular, [`image::RgbaImage`] is compressed from [`PixelFormat::RGBA`], so TurboJPEG simply
+/// skips the alpha bytes while reading the pixels; there is no need to convert the image to RGB
+/// first.
+///
 /// # Example
 ///
 /// ```
@@ -74,6 +232,19 @@ pub fn compress_image<P>(
     subsamp: Subsamp,
 ) -> Result<OwnedBuf>
     where P: JpegPixel + 'static
+{
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(quality);
+    compressor.set_subsamp(subsamp);
+    compress_image_with(&mut compressor, image_buf)
+}
+
+/// Compresses an [`image::ImageBuffer`] into JPEG using the given `compressor`.
+pub(crate) fn compress_image_with<P>(
+    compressor: &mut Compressor,
+    image_buf: &image::ImageBuffer<P, Vec<u8>>,
+) -> Result<OwnedBuf>
+    where P: JpegPixel + 'static
 {
     let (width, height) = image_buf.dimensions();
     let format = P::PIXEL_FORMAT;
@@ -84,15 +255,139 @@ pub fn compress_image<P>(
         height: height as usize,
         format,
     };
+    compressor.compress_to_owned(image)
+}
+
+/// Compresses an owned [`image::ImageBuffer`] into JPEG.
+///
+/// This is the same as [`compress_image()`], but it takes ownership of the image and drops it as
+/// soon as the compression is done. This is handy in async code, where holding a borrow of the
+/// image across an `.await` point is inconvenient.
+///
+/// # Example
+///
+/// ```
+/// // create an `image::GrayImage`
+/// let image = image::GrayImage::from_fn(256, 256, |x, y| image::Luma([(x ^ y) as u8]));
+///
+/// // compress `image` into grayscale JPEG with quality 90, consuming the image
+/// let jpeg_data = turbojpeg::compress_image_owned(image, 90, turbojpeg::Subsamp::Gray)?;
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+pub fn compress_image_owned<P>(
+    image_buf: image::ImageBuffer<P, Vec<u8>>,
+    quality: i32,
+    subsamp: Subsamp,
+) -> Result<OwnedBuf>
+    where P: JpegPixel + 'static
+{
+    compress_image(&image_buf, quality, subsamp)
+}
 
+/// Compresses an [`image::RgbaImage`] into a color JPEG and a separate grayscale JPEG with the
+/// alpha channel.
+///
+/// JPEG cannot store the alpha channel, so this function compresses the RGB channels of the image
+/// into one JPEG (with the given `quality` and chrominance `subsamp`) and the alpha channel into
+/// another, grayscale JPEG (with the same `quality`). Returns the color and the alpha JPEG, in
+/// this order. Use [`decompress_image_with_alpha()`] to combine them into an RGBA image again.
+///
+/// The alpha channel is compressed lossily like the colors, so sharp edges of the mask may get
+/// slightly blurred; use a high quality if you need a precise mask.
+///
+/// # Example
+///
+/// ```
+/// // create an `image::RgbaImage` with an alpha gradient
+/// let image = image::RgbaImage::from_fn(256, 256, |x, y| {
+///     image::Rgba([x as u8, y as u8, 128, x as u8])
+/// });
+///
+/// // compress it into two JPEGs and decompress them back
+/// let (color_data, alpha_data) =
+///     turbojpeg::compress_image_with_alpha(&image, 95, turbojpeg::Subsamp::Sub2x2)?;
+/// let restored = turbojpeg::decompress_image_with_alpha(&color_data, &alpha_data)?;
+/// assert_eq!(restored.dimensions(), (256, 256));
+/// assert!(restored.get_pixel(200, 10)[3].abs_diff(200) <= 2);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+pub fn compress_image_with_alpha(
+    image_buf: &image::RgbaImage,
+    quality: i32,
+    subsamp: Subsamp,
+) -> Result<(OwnedBuf, OwnedBuf)> {
+    let color_data = compress_image(image_buf, quality, subsamp)?;
+
+    let (width, height) = image_buf.dimensions();
+    let alpha: Vec<u8> = image_buf.as_raw().chunks_exact(4).map(|pixel| pixel[3]).collect();
+    let alpha = Image {
+        pixels: &alpha[..],
+        width: width as usize,
+        pitch: width as usize,
+        height: height as usize,
+        format: PixelFormat::GRAY,
+    };
     let mut compressor = Compressor::new()?;
     compressor.set_quality(quality);
-    compressor.set_subsamp(subsamp);
-    compressor.compress_to_owned(image)
+    compressor.set_subsamp(Subsamp::Gray);
+    let alpha_data = compressor.compress_to_owned(alpha)?;
+
+    Ok((color_data, alpha_data))
+}
+
+/// Decompresses a color JPEG and a grayscale JPEG with the alpha channel into an
+/// [`image::RgbaImage`].
+///
+/// This is the inverse of [`compress_image_with_alpha()`]. The luminance of `alpha_data` is used
+/// as the alpha channel, so it may also be a color JPEG. Returns [`Error::AlphaSizeMismatch`] if
+/// the two images do not have the same size.
+#[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+pub fn decompress_image_with_alpha(color_data: &[u8], alpha_data: &[u8]) -> Result<image::RgbaImage> {
+    let mut decompressor = Decompressor::new()?;
+    let color_header = decompressor.read_header(color_data)?;
+    let alpha_header = decompressor.read_header(alpha_data)?;
+    let color_size = (color_header.width, color_header.height);
+    let alpha_size = (alpha_header.width, alpha_header.height);
+    if color_size != alpha_size {
+        return Err(Error::AlphaSizeMismatch(color_size, alpha_size))
+    }
+
+    let mut image_buf: image::RgbaImage = decompress_image_with(&mut decompressor, color_data)?;
+    let mut alpha = Vec::new();
+    decompressor.decompress_to_slice(alpha_data, PixelFormat::GRAY, &mut alpha)?;
+    for (pixel, &a) in image_buf.chunks_exact_mut(4).zip(alpha.iter()) {
+        pixel[3] = a;
+    }
+    Ok(image_buf)
 }
 
 /// Trait implemented for [`image::Pixel`s][image::Pixel] that correspond to a [`PixelFormat`] supported
 /// by TurboJPEG.
+///
+/// The pixel format is an associated constant, so it is known at compile time: functions such as
+/// [`compress_image()`] and [`decompress_image()`] take it from the pixel type of the
+/// [`image::ImageBuffer`] without any runtime dispatch, and you can do the same in your own
+/// generic code.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{JpegPixel, PixelFormat};
+/// assert_eq!(image::Rgb::<u8>::PIXEL_FORMAT, PixelFormat::RGB);
+/// assert_eq!(image::Rgba::<u8>::PIXEL_FORMAT, PixelFormat::RGBA);
+/// assert_eq!(image::Luma::<u8>::PIXEL_FORMAT, PixelFormat::GRAY);
+///
+/// // a generic function that works with any supported pixel type
+/// fn bytes_per_row<P: JpegPixel>(image: &image::ImageBuffer<P, Vec<u8>>) -> usize {
+///     image.width() as usize * P::PIXEL_FORMAT.size()
+/// }
+/// assert_eq!(bytes_per_row(&image::RgbaImage::new(10, 10)), 40);
+/// assert_eq!(bytes_per_row(&image::GrayImage::new(10, 10)), 10);
+/// ```
 #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
 pub trait JpegPixel: image::Pixel<Subpixel = u8> {
     /// The TurboJPEG pixel format that corresponds to this pixel type.
@@ -0,0 +1,43 @@
+use crate::{Compressor, Decompressor, Image, PixelFormat, Subsamp, Transformer};
+use crate::common::{Error, Result};
+
+/// Check that TurboJPEG works, so that a broken installation is detected at startup.
+///
+/// This creates a compressor, a decompressor and a transformer, compresses a tiny image and
+/// decompresses it again. Call this function when your program starts (for example, before a
+/// server starts accepting requests) to fail early instead of on the first real image. Calling
+/// this function is optional, the library does not need to be initialized.
+///
+/// This crate links to TurboJPEG when the program is built, so a missing library or a library
+/// without the functions that this crate uses is reported by the linker or the dynamic loader,
+/// before this function is called. TurboJPEG does not report its version, so this function checks
+/// that the library behaves as expected instead, and returns [`Error::SelfTestFailed`] if it
+/// does not.
+///
+/// # Example
+///
+/// ```
+/// turbojpeg::init()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn init() -> Result<()> {
+    let image = Image::mandelbrot(16, 16, PixelFormat::RGB);
+    let mut compressor = Compressor::new()?;
+    compressor.set_subsamp(Subsamp::Sub2x2);
+    let jpeg_data = compressor.compress_to_vec(image.as_deref())?;
+
+    Transformer::new()?;
+
+    let mut decompressor = Decompressor::new()?;
+    let header = decompressor.read_header(&jpeg_data)?;
+    if (header.width, header.height, header.subsamp) != (16, 16, Subsamp::Sub2x2) {
+        return Err(Error::SelfTestFailed(format!("unexpected header {:?}", header)))
+    }
+
+    let mut output = Image {
+        pixels: vec![0; image.pixels.len()],
+        .. image
+    };
+    decompressor.decompress(&jpeg_data, output.as_deref_mut())?;
+    Ok(())
+}
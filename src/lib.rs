@@ -47,6 +47,9 @@
 //! argument for compression, `Image<&out [u8]>` as output argument for decompression, and you may
 //! also find `Image<Vec<u8>>` useful as an owned container of image data in you application.
 //!
+//! You can create an [`Image`] with [`Image::new()`], which computes the pitch from the width and
+//! the pixel format, or with [`Image::with_pitch()`] if the rows are padded.
+//!
 //! # Operations
 //!
 //! - **Decompress** images from JPEG using [`decompress()`] or [`Decompressor`].
@@ -54,8 +57,10 @@
 //! - **Transform** images without recompression using [`transform()`] or [`Transformer`]. The
 //! transformations are described in the [`Transform`] struct.
 //! - **Read header** of JPEG image to get its size without decompression using
-//! [`Decompressor::read_header()`] or [`read_header()`].
-//! 
+//!   [`Decompressor::read_header()`] or [`read_header()`], or parse it in pure Rust using
+//!   [`parse_header()`].
+//! - **Iterate markers** of a JPEG image with their positions in the data using [`markers()`].
+//!
 //! # The [`OutputBuf`] and [`OwnedBuf`] types
 //!
 //! During decompression, we need to write the produced JPEG data into some memory buffer. You have
@@ -78,10 +83,16 @@
 //! # Features
 //!
 //! - `image`: enables the optional dependency on the [`image`][image-rs] crate.
+//! - `tracing`: instruments compression, decompression and transformation with
+//!   [`tracing`][tracing] spans (at the debug level).
+//! - `tokio`: together with `image`, enables [`compress_image_async()`] and
+//!   [`decompress_image_async()`], which run on the blocking thread pool of [tokio].
 //! - `pkg-config`: uses pkg-config to find the `libturbojpeg` library.
 //! - `bindgen`: uses [bindgen] to generate the `libturbojpeg` bindings.
 //!
 //! [bindgen]: https://rust-lang.github.io/rust-bindgen/
+//! [tokio]: https://docs.rs/tokio
+//! [tracing]: https://docs.rs/tracing
 #![warn(missing_docs)]
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
@@ -92,17 +103,41 @@ mod buf;
 mod common;
 mod compress;
 mod decompress;
+mod float;
 mod image;
+mod init;
+mod marker;
+mod metrics;
+mod mjpeg;
+mod mpo;
+mod sink;
 mod transform;
-pub use self::buf::{OwnedBuf, OutputBuf};
-pub use self::common::{PixelFormat, Subsamp, Colorspace, Result, Error};
-pub use self::compress::{Compressor, compress, compressed_buf_len};
-pub use self::decompress::{Decompressor, DecompressHeader, decompress, read_header};
-pub use self::image::Image;
-pub use self::transform::{Transformer, Transform, TransformOp, TransformCrop, transform};
+mod yuv;
+pub use self::buf::{OwnedBuf, OutputBuf, GrowthPolicy};
+pub use self::common::{PixelFormat, Channel, Subsamp, Colorspace, DctMethod, Density, DensityUnit, EntropyCoding, Upsampling, Result, Error, ErrorSeverity};
+pub use self::compress::{Compressor, CompressionProfile, EncodeOptions, compress, compress_gray, compressed_buf_len, encode};
+pub use self::decompress::{Decompressor, DecompressHeader, DecodeOutcome, decode, decompress, decompress_gray, read_header, scan_headers};
+pub use self::float::{Layout, Normalization};
+pub use self::image::{Image, convert_pixels};
+pub use self::init::init;
+pub use self::marker::{Marker, Markers, markers, parse_header};
+pub use self::metrics::{Metrics, Operation, set_metrics_sink};
+pub use self::mjpeg::MjpegWriter;
+pub use self::mpo::{MpoWriter, MpoKind};
+pub use self::sink::PixelSink;
+pub use self::transform::{Transformer, Transform, TransformOp, TransformCrop, ActualCrop, transform};
+pub use self::yuv::{YuvImage, PlaneView};
 
 #[cfg(feature = "image")]
 mod image_rs;
 #[cfg(feature = "image")]
-pub use self::image_rs::{JpegPixel, compress_image, decompress_image};
+pub use self::image_rs::{
+    JpegPixel, compress_image, compress_image_owned, compress_image_with_alpha, decompress_image,
+    decompress_image_with_alpha, decompress_luma_preview, thumbnail_image,
+};
+
+#[cfg(all(feature = "image", feature = "tokio"))]
+mod tokio_rs;
+#[cfg(all(feature = "image", feature = "tokio"))]
+pub use self::tokio_rs::{compress_image_async, decompress_image_async};
 
@@ -0,0 +1,561 @@
+use crate::common::{Colorspace, Density, DensityUnit, EntropyCoding, Subsamp, Result, Error};
+use crate::decompress::DecompressHeader;
+
+/// Marker segment in JPEG data.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Segment<'a> {
+    /// Marker code (the byte that follows 0xFF).
+    pub marker: u8,
+    /// Payload of the segment, without the marker and length bytes. This is empty for markers that
+    /// do not have a payload (such as SOI or EOI).
+    pub data: &'a [u8],
+    /// Offset of the marker (its 0xFF byte) in the JPEG data.
+    pub offset: usize,
+    /// Length of the whole segment, including the marker and length bytes.
+    pub length: usize,
+}
+
+pub(crate) const SOI: u8 = 0xd8;
+pub(crate) const EOI: u8 = 0xd9;
+pub(crate) const SOS: u8 = 0xda;
+pub(crate) const DQT: u8 = 0xdb;
+pub(crate) const APP0: u8 = 0xe0;
+pub(crate) const APP1: u8 = 0xe1;
+pub(crate) const APP2: u8 = 0xe2;
+pub(crate) const APP14: u8 = 0xee;
+
+/// Returns true for markers that start a frame (SOF0 to SOF15).
+pub(crate) fn is_sof(marker: u8) -> bool {
+    // 0xc4 (DHT), 0xc8 (JPG) and 0xcc (DAC) share the range, but they are not SOF markers
+    (0xc0..=0xcf).contains(&marker) && !matches!(marker, 0xc4 | 0xc8 | 0xcc)
+}
+
+/// Returns an iterator over the marker segments in `data`.
+///
+/// The entropy-coded data that follows the SOS markers is skipped. The iterator stops after the
+/// EOI marker, at the end of the data, or after it returns an error.
+pub(crate) fn segments(data: &[u8]) -> Segments<'_> {
+    Segments { data, pos: 0, done: false }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Segments<'a> {
+    data: &'a [u8],
+    pos: usize,
+    done: bool,
+}
+
+impl<'a> Segments<'a> {
+    fn next_segment(&mut self) -> Result<Option<Segment<'a>>> {
+        let data = self.data;
+        if self.pos == 0 {
+            if data.len() < 2 || data[0] != 0xff || data[1] != SOI {
+                return Err(Error::BadHeader("missing SOI marker"))
+            }
+            self.pos = 2;
+            return Ok(Some(Segment { marker: SOI, data: &[], offset: 0, length: 2 }))
+        }
+
+        // skip any garbage before the marker and the fill bytes (0xff) that may precede it
+        while self.pos < data.len() && data[self.pos] != 0xff {
+            self.pos += 1;
+        }
+        while self.pos + 1 < data.len() && data[self.pos + 1] == 0xff {
+            self.pos += 1;
+        }
+        if self.pos + 1 >= data.len() {
+            return Ok(None)
+        }
+
+        let offset = self.pos;
+        let marker = data[offset + 1];
+        if matches!(marker, 0x01 | 0xd0..=0xd7 | SOI | EOI) {
+            self.pos = offset + 2;
+            if marker == EOI {
+                self.done = true;
+            }
+            return Ok(Some(Segment { marker, data: &[], offset, length: 2 }))
+        }
+
+        if offset + 4 > data.len() {
+            return Err(Error::BadHeader("truncated marker segment"))
+        }
+        let len = u16::from_be_bytes([data[offset + 2], data[offset + 3]]) as usize;
+        if len < 2 {
+            return Err(Error::BadHeader("invalid length of marker segment"))
+        }
+        let end = offset + 2 + len;
+        if end > data.len() {
+            return Err(Error::BadHeader("truncated marker segment"))
+        }
+        self.pos = end;
+
+        if marker == SOS {
+            self.skip_entropy_coded_data();
+        }
+
+        Ok(Some(Segment { marker, data: &data[offset + 4..end], offset, length: end - offset }))
+    }
+
+    fn skip_entropy_coded_data(&mut self) {
+        let data = self.data;
+        while self.pos + 1 < data.len() {
+            if data[self.pos] == 0xff {
+                match data[self.pos + 1] {
+                    // stuffed zero byte or a restart marker, both are part of the entropy-coded data
+                    0x00 | 0xd0..=0xd7 => self.pos += 2,
+                    // fill byte
+                    0xff => self.pos += 1,
+                    _ => return,
+                }
+            } else {
+                self.pos += 1;
+            }
+        }
+        self.pos = data.len();
+    }
+}
+
+impl<'a> Iterator for Segments<'a> {
+    type Item = Result<Segment<'a>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None
+        }
+        let res = self.next_segment().transpose();
+        if !matches!(res, Some(Ok(_))) {
+            self.done = true;
+        }
+        res
+    }
+}
+
+/// Marker segment in JPEG data, returned by [`markers()`].
+///
+/// Besides the payload, the marker records its position in the JPEG data, so that you can replace
+/// the segment (for example an EXIF block) in place without touching the rest of the image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Marker<'a> {
+    /// Marker code, the byte that follows 0xFF (for example 0xE1 for APP1).
+    pub kind: u8,
+    /// Payload of the segment, without the marker and length bytes. This is empty for markers that
+    /// do not have a payload (such as SOI or EOI).
+    pub data: &'a [u8],
+    /// Offset of the marker (its 0xFF byte) in the JPEG data.
+    pub offset: usize,
+    /// Length of the whole segment in bytes, including the marker and length bytes, so the segment
+    /// occupies `offset..offset + length` in the JPEG data.
+    pub length: usize,
+}
+
+/// Returns an iterator over the marker segments in `jpeg_data`.
+///
+/// The iterator yields all markers from SOI up to and including EOI in the order in which they
+/// appear in the data. The entropy-coded data that follows the SOS markers (including the restart
+/// markers inside of it) is skipped. The iterator stops at the end of the data or after it returns
+/// an error for malformed data.
+///
+/// # Example
+///
+/// ```
+/// // read JPEG data from file
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+///
+/// // the image starts with the SOI marker, followed by the JFIF APP0 segment
+/// let markers = turbojpeg::markers(&jpeg_data).collect::<turbojpeg::Result<Vec<_>>>()?;
+/// assert_eq!((markers[0].kind, markers[0].offset, markers[0].length), (0xd8, 0, 2));
+/// assert_eq!((markers[1].kind, markers[1].offset, markers[1].length), (0xe0, 2, 18));
+/// assert_eq!(markers.last().unwrap().kind, 0xd9);
+///
+/// // overwrite the density in the JFIF payload (which follows the marker and length bytes)
+/// let jfif = markers[1];
+/// let mut edited_data = jpeg_data.clone();
+/// edited_data[jfif.offset + 4..][7..12].copy_from_slice(&[1, 0, 72, 0, 72]);
+/// let density = turbojpeg::Decompressor::new()?.read_density(&edited_data)?.unwrap();
+/// assert_eq!((density.x, density.y), (72, 72));
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn markers(jpeg_data: &[u8]) -> Markers<'_> {
+    Markers { segments: segments(jpeg_data) }
+}
+
+/// Iterator over the marker segments in JPEG data, returned by [`markers()`].
+#[derive(Debug, Clone)]
+pub struct Markers<'a> {
+    segments: Segments<'a>,
+}
+
+impl<'a> Iterator for Markers<'a> {
+    type Item = Result<Marker<'a>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.segments.next().map(|segment| segment.map(|segment| Marker {
+            kind: segment.marker,
+            data: segment.data,
+            offset: segment.offset,
+            length: segment.length,
+        }))
+    }
+}
+
+/// Frame header from the SOF marker.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub num_components: usize,
+    pub component_ids: [u8; 4],
+    pub sampling_factors: [(u8, u8); 4],
+    pub entropy_coding: EntropyCoding,
+}
+
+impl Frame {
+    pub(crate) fn parse(marker: u8, data: &[u8]) -> Result<Frame> {
+        let entropy_coding = EntropyCoding::from_sof(marker)
+            .ok_or(Error::BadHeader("unknown SOF marker"))?;
+        if data.len() < 6 {
+            return Err(Error::BadHeader("truncated SOF marker"))
+        }
+        let height = u16::from_be_bytes([data[1], data[2]]) as usize;
+        let width = u16::from_be_bytes([data[3], data[4]]) as usize;
+        let num_components = data[5] as usize;
+        if width == 0 || height == 0 {
+            return Err(Error::BadHeader("invalid image size"))
+        }
+        if !matches!(num_components, 1 | 3 | 4) {
+            return Err(Error::BadHeader("unsupported number of components"))
+        }
+        if data.len() < 6 + 3 * num_components {
+            return Err(Error::BadHeader("truncated SOF marker"))
+        }
+
+        let mut component_ids = [0; 4];
+        let mut sampling_factors = [(0, 0); 4];
+        for i in 0..num_components {
+            let component = &data[6 + 3 * i..];
+            component_ids[i] = component[0];
+            sampling_factors[i] = (component[1] >> 4, component[1] & 0xf);
+        }
+
+        Ok(Frame { width, height, num_components, component_ids, sampling_factors, entropy_coding })
+    }
+}
+
+/// Parse the JPEG header without decompressing the image and without using TurboJPEG.
+///
+/// This is a pure Rust alternative to [`read_header()`][crate::read_header] that reads the markers
+/// up to the first scan and determines the colorspace and chrominance subsampling using the same
+/// rules as libjpeg-turbo. It does not allocate and does not need to initialize a TurboJPEG
+/// instance, so it is faster when you need to read the headers of many JPEG files.
+///
+/// # Example
+///
+/// ```
+/// // read JPEG data from file
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+///
+/// // parse the JPEG header
+/// let header = turbojpeg::parse_header(&jpeg_data)?;
+/// assert_eq!((header.width, header.height), (384, 256));
+/// assert_eq!(header.subsamp, turbojpeg::Subsamp::None);
+/// assert_eq!(header.colorspace, turbojpeg::Colorspace::YCbCr);
+/// assert_eq!(header.entropy_coding, turbojpeg::EntropyCoding::ProgressiveHuffman);
+/// assert_eq!(header.num_components, 3);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn parse_header(jpeg_data: &[u8]) -> Result<DecompressHeader> {
+    let mut frame = None;
+    let mut saw_jfif = false;
+    let mut adobe_transform = None;
+    for segment in segments(jpeg_data) {
+        let segment = segment?;
+        match segment.marker {
+            APP0 if segment.data.starts_with(JFIF_SIGNATURE) => saw_jfif = true,
+            APP14 if segment.data.starts_with(b"Adobe") && segment.data.len() >= 12 =>
+                adobe_transform = Some(segment.data[11]),
+            marker if is_sof(marker) && frame.is_none() => frame = Some(Frame::parse(marker, segment.data)?),
+            SOS | EOI => break,
+            _ => {},
+        }
+    }
+
+    let frame = frame.ok_or(Error::BadHeader("missing SOF marker"))?;
+    let colorspace = frame_colorspace(&frame, saw_jfif, adobe_transform);
+    let subsamp = frame_subsamp(&frame, colorspace)
+        .ok_or(Error::BadHeader("could not determine subsampling"))?;
+    Ok(DecompressHeader {
+        width: frame.width,
+        height: frame.height,
+        subsamp,
+        colorspace,
+        entropy_coding: frame.entropy_coding,
+        num_components: frame.num_components as u8,
+    })
+}
+
+/// Reads the image dimensions from the first SOF marker in `jpeg_data`.
+pub(crate) fn dimensions(jpeg_data: &[u8]) -> Result<(usize, usize)> {
+    for segment in segments(jpeg_data) {
+        let segment = segment?;
+        match segment.marker {
+            marker if is_sof(marker) => {
+                let data = segment.data;
+                if data.len() < 5 {
+                    return Err(Error::BadHeader("truncated SOF marker"))
+                }
+                let height = u16::from_be_bytes([data[1], data[2]]) as usize;
+                let width = u16::from_be_bytes([data[3], data[4]]) as usize;
+                return Ok((width, height))
+            },
+            SOS | EOI => break,
+            _ => {},
+        }
+    }
+    Err(Error::BadHeader("missing SOF marker"))
+}
+
+/// Returns true if `jpeg_data` ends before the EOI marker, which means that it was truncated.
+pub(crate) fn is_truncated(jpeg_data: &[u8]) -> bool {
+    !segments(jpeg_data).any(|segment| matches!(segment, Ok(Segment { marker: EOI, .. })))
+}
+
+/// Returns the offset in `jpeg_data` where an application marker should be inserted: after the
+/// SOI marker and the JFIF APP0 marker (which must be the first marker in JFIF files).
+pub(crate) fn app_insert_offset(jpeg_data: &[u8]) -> usize {
+    let mut offset = 2;
+    if jpeg_data.len() >= 6 && jpeg_data[2..4] == [0xff, APP0] {
+        offset += 2 + u16::from_be_bytes([jpeg_data[4], jpeg_data[5]]) as usize;
+    }
+    offset.min(jpeg_data.len())
+}
+
+/// Returns the offset after the leading APP0 (JFIF) and APP1 (EXIF, XMP) segments in
+/// `jpeg_data`, which is where an APP2 segment should be inserted.
+pub(crate) fn app_insert_offset_after_exif(jpeg_data: &[u8]) -> usize {
+    let mut offset = 2;
+    for segment in segments(jpeg_data).skip(1) {
+        match segment {
+            Ok(Segment { marker: APP0 | APP1, data, .. }) =>
+                offset = data.as_ptr() as usize - jpeg_data.as_ptr() as usize + data.len(),
+            _ => break,
+        }
+    }
+    offset.min(jpeg_data.len())
+}
+
+/// Signature at the start of the JFIF APP0 segment.
+const JFIF_SIGNATURE: &[u8] = b"JFIF\0";
+
+/// Reads the density from the JFIF APP0 marker in `jpeg_data`.
+pub(crate) fn find_jfif_density(jpeg_data: &[u8]) -> Result<Option<Density>> {
+    for segment in segments(jpeg_data) {
+        let segment = segment?;
+        match segment.marker {
+            APP0 if segment.data.starts_with(JFIF_SIGNATURE) => {
+                // signature, version, unit, x density, y density
+                let data = segment.data;
+                if data.len() < 12 {
+                    return Err(Error::BadHeader("truncated JFIF marker"))
+                }
+                let unit = DensityUnit::from_u8(data[7])
+                    .ok_or(Error::BadHeader("invalid unit of JFIF density"))?;
+                let x = u16::from_be_bytes([data[8], data[9]]);
+                let y = u16::from_be_bytes([data[10], data[11]]);
+                return Ok(Some(Density { x, y, unit }))
+            },
+            SOS | EOI => break,
+            _ => {},
+        }
+    }
+    Ok(None)
+}
+
+/// Overwrites the density in the JFIF APP0 marker at the start of `jpeg_data`.
+///
+/// Returns false if the data does not start with a JFIF marker.
+pub(crate) fn set_jfif_density(jpeg_data: &mut [u8], x: u16, y: u16, unit: u8) -> bool {
+    // SOI, APP0 marker and length, signature, version, unit, x density, y density
+    let jfif = match jpeg_data.get_mut(2..18) {
+        Some(jfif) if jfif[..2] == [0xff, APP0] && &jfif[4..9] == JFIF_SIGNATURE => jfif,
+        _ => return false,
+    };
+    jfif[11] = unit;
+    jfif[12..14].copy_from_slice(&x.to_be_bytes());
+    jfif[14..16].copy_from_slice(&y.to_be_bytes());
+    true
+}
+
+/// Signature at the start of an APP1 segment with an XMP packet.
+const XMP_SIGNATURE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// Finds the XMP packet in `jpeg_data`.
+pub(crate) fn find_xmp(jpeg_data: &[u8]) -> Result<Option<&[u8]>> {
+    for segment in segments(jpeg_data) {
+        let segment = segment?;
+        if segment.marker == APP1 && segment.data.starts_with(XMP_SIGNATURE) {
+            return Ok(Some(&segment.data[XMP_SIGNATURE.len()..]))
+        }
+    }
+    Ok(None)
+}
+
+/// Natural (row-major) index of each coefficient in the zig-zag order.
+const ZIGZAG_TO_NATURAL: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5,
+    12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// Reads the quantization tables from the DQT markers before the first SOS marker in
+/// `jpeg_data`.
+///
+/// The tables are returned in natural order, indexed by their slot (0 to 3). Slots that are not
+/// defined, but are followed by a defined slot, are filled with zeros.
+pub(crate) fn quant_tables(jpeg_data: &[u8]) -> Result<Vec<[u16; 64]>> {
+    let mut tables = Vec::new();
+    for segment in segments(jpeg_data) {
+        let segment = segment?;
+        match segment.marker {
+            DQT => {},
+            SOS | EOI => break,
+            _ => continue,
+        }
+
+        let mut data = segment.data;
+        while let Some((&pq_tq, rest)) = data.split_first() {
+            let (precision, slot) = (pq_tq >> 4, (pq_tq & 0x0f) as usize);
+            let value_len = match precision {
+                0 => 1,
+                1 => 2,
+                _ => return Err(Error::BadHeader("invalid precision of quantization table")),
+            };
+            if slot > 3 {
+                return Err(Error::BadHeader("invalid quantization table slot"))
+            }
+            if rest.len() < 64 * value_len {
+                return Err(Error::BadHeader("truncated quantization table"))
+            }
+
+            let mut table = [0; 64];
+            for (zigzag, value) in rest[..64 * value_len].chunks_exact(value_len).enumerate() {
+                table[ZIGZAG_TO_NATURAL[zigzag]] = match *value {
+                    [value] => value as u16,
+                    [hi, lo] => u16::from_be_bytes([hi, lo]),
+                    _ => unreachable!(),
+                };
+            }
+            if tables.len() <= slot {
+                tables.resize(slot + 1, [0; 64]);
+            }
+            tables[slot] = table;
+            data = &rest[64 * value_len..];
+        }
+    }
+    Ok(tables)
+}
+
+/// Reads the frame header from the first SOF marker in `jpeg_data`.
+pub(crate) fn first_frame(jpeg_data: &[u8]) -> Result<Frame> {
+    for segment in segments(jpeg_data) {
+        let segment = segment?;
+        match segment.marker {
+            marker if is_sof(marker) => return Frame::parse(marker, segment.data),
+            SOS | EOI => break,
+            _ => {},
+        }
+    }
+    Err(Error::BadHeader("missing SOF marker"))
+}
+
+/// Returns a copy of `jpeg_data` with an Adobe APP14 marker that makes libjpeg interpret the
+/// image in the given `colorspace`.
+///
+/// Returns `None` if the colorspace of the image is not ambiguous (the image has a JFIF or an
+/// Adobe marker), if the `colorspace` cannot be expressed for the number of components in the
+/// image, or if the header cannot be parsed.
+pub(crate) fn insert_adobe_transform(jpeg_data: &[u8], colorspace: Colorspace) -> Option<Vec<u8>> {
+    let mut num_components = None;
+    for segment in segments(jpeg_data) {
+        let segment = segment.ok()?;
+        match segment.marker {
+            APP0 if segment.data.starts_with(JFIF_SIGNATURE) => return None,
+            APP14 if segment.data.starts_with(b"Adobe") => return None,
+            marker if is_sof(marker) && num_components.is_none() =>
+                num_components = Some(Frame::parse(marker, segment.data).ok()?.num_components),
+            SOS | EOI => break,
+            _ => {},
+        }
+    }
+
+    let transform = match (num_components?, colorspace) {
+        (3, Colorspace::RGB) | (4, Colorspace::CMYK) => 0,
+        (3, Colorspace::YCbCr) => 1,
+        (4, Colorspace::YCCK) => 2,
+        _ => return None,
+    };
+
+    // signature, version 100, flags0, flags1 and the color transform
+    let mut adobe = vec![0xff, APP14, 0, 14];
+    adobe.extend_from_slice(b"Adobe\0d\0\0\0\0");
+    adobe.push(transform);
+
+    let offset = app_insert_offset(jpeg_data);
+    let mut data = Vec::with_capacity(jpeg_data.len() + adobe.len());
+    data.extend_from_slice(&jpeg_data[..offset]);
+    data.extend_from_slice(&adobe);
+    data.extend_from_slice(&jpeg_data[offset..]);
+    Some(data)
+}
+
+/// Determines the colorspace of the frame in the same way as libjpeg.
+fn frame_colorspace(frame: &Frame, saw_jfif: bool, adobe_transform: Option<u8>) -> Colorspace {
+    match frame.num_components {
+        1 => Colorspace::Gray,
+        3 => match (saw_jfif, adobe_transform) {
+            (true, _) => Colorspace::YCbCr,
+            (false, Some(0)) => Colorspace::RGB,
+            (false, Some(_)) => Colorspace::YCbCr,
+            (false, None) => match frame.component_ids[..3] {
+                [b'R', b'G', b'B'] => Colorspace::RGB,
+                _ => Colorspace::YCbCr,
+            },
+        },
+        _ => match adobe_transform {
+            Some(0) | None => Colorspace::CMYK,
+            Some(_) => Colorspace::YCCK,
+        },
+    }
+}
+
+/// Determines the chrominance subsampling of the frame in the same way as TurboJPEG.
+fn frame_subsamp(frame: &Frame, colorspace: Colorspace) -> Option<Subsamp> {
+    let factors = &frame.sampling_factors[..frame.num_components];
+    if factors.len() == 1 {
+        return Some(Subsamp::Gray)
+    }
+
+    // the K component of CMYK and YCCK images is sampled like the luminance component
+    let has_k = factors.len() == 4 && matches!(colorspace, Colorspace::CMYK | Colorspace::YCCK);
+    let luma = (factors[0].0 as usize, factors[0].1 as usize);
+    let others_match = |chroma: (usize, usize), k: (usize, usize)| {
+        factors[1..].iter().enumerate().all(|(i, &(h, v))| {
+            let expected = if has_k && i == 2 { k } else { chroma };
+            (h as usize, v as usize) == expected
+        })
+    };
+
+    [Subsamp::None, Subsamp::Sub2x1, Subsamp::Sub2x2, Subsamp::Sub1x2, Subsamp::Sub4x1]
+        .into_iter()
+        .find(|&subsamp| {
+            let mcu = (subsamp.mcu_width() / 8, subsamp.mcu_height() / 8);
+            // standard sampling factors
+            (luma == mcu && others_match((1, 1), mcu))
+            // 4:2:2 and 4:4:0 with sampling factors specified in multiples of 2
+            || (luma == (2, 2) && matches!(subsamp, Subsamp::Sub2x1 | Subsamp::Sub1x2)
+                && others_match((mcu.1, mcu.0), (2, 2)))
+            // 4:4:4 with all components sampled with the same non-standard factors
+            || (subsamp == Subsamp::None && luma.0 * luma.1 <= 3 && others_match(luma, luma))
+        })
+}
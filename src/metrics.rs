@@ -0,0 +1,83 @@
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Kind of operation described by [`Metrics`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Operation {
+    /// Compression of an image into JPEG.
+    Compress,
+    /// Decompression of a JPEG image.
+    Decompress,
+    /// Lossless transformation of a JPEG image.
+    Transform,
+}
+
+/// Measurements of a single operation, passed to the sink registered by [`set_metrics_sink()`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Metrics {
+    /// The operation that was performed.
+    pub operation: Operation,
+    /// Number of bytes in the input (pixel data for compression, JPEG data otherwise).
+    pub input_len: usize,
+    /// Number of bytes in the output (JPEG data for compression and transformation, pixel data
+    /// for decompression).
+    pub output_len: usize,
+    /// Number of pixels in the image (width times height). This is 0 for transformations, which
+    /// do not decode the image.
+    pub pixels: usize,
+    /// Time spent in TurboJPEG.
+    pub elapsed: Duration,
+}
+
+type Sink = Box<dyn Fn(Metrics) + Send + Sync>;
+
+static SINK: OnceLock<Sink> = OnceLock::new();
+
+/// Register a global callback that receives [`Metrics`] for every successful operation.
+///
+/// The callback is called after every successful compression, decompression and transformation in
+/// any thread, so it should be fast (for example, it can update a few counters or histograms).
+/// The sink can be registered only once; this function returns `false` and drops `sink` if a sink
+/// has already been registered. When no sink is registered, the operations don't even read the
+/// clock, so the overhead is negligible.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// static DECOMPRESSED_PIXELS: AtomicUsize = AtomicUsize::new(0);
+///
+/// turbojpeg::set_metrics_sink(|metrics| {
+///     if metrics.operation == turbojpeg::Operation::Decompress {
+///         DECOMPRESSED_PIXELS.fetch_add(metrics.pixels, Ordering::Relaxed);
+///     }
+/// });
+///
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// turbojpeg::decompress(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+/// assert_eq!(DECOMPRESSED_PIXELS.load(Ordering::Relaxed), 384 * 256);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn set_metrics_sink(sink: impl Fn(Metrics) + Send + Sync + 'static) -> bool {
+    SINK.set(Box::new(sink)).is_ok()
+}
+
+/// Starts measuring an operation, returns `None` if no sink is registered.
+pub(crate) fn start() -> Option<Instant> {
+    SINK.get().map(|_| Instant::now())
+}
+
+/// Passes the measurements of an operation started by [`start()`] to the sink.
+pub(crate) fn record(
+    start: Option<Instant>,
+    operation: Operation,
+    input_len: usize,
+    output_len: usize,
+    pixels: usize,
+) {
+    if let (Some(start), Some(sink)) = (start, SINK.get()) {
+        sink(Metrics { operation, input_len, output_len, pixels, elapsed: start.elapsed() });
+    }
+}
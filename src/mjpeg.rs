@@ -0,0 +1,81 @@
+use std::io::Write;
+use crate::{Compressor, Image, OutputBuf};
+use crate::common::Result;
+
+/// Writer of Motion JPEG (MJPEG) streams.
+///
+/// The writer compresses every frame into JPEG and writes it into the underlying
+/// [`Write`][std::io::Write]r. The stream is a raw MJPEG stream, which is just a sequence of
+/// complete JPEG images without any container around them; tools such as ffmpeg can read it (as
+/// the `mjpeg` format) and remux it into a container such as AVI or MKV.
+///
+/// The frames are compressed with a single [`Compressor`] into a single owned [`OutputBuf`],
+/// which are reused for all frames, so after the first few frames, writing a frame does not
+/// allocate.
+///
+/// # Example
+///
+/// ```
+/// let mut writer = turbojpeg::MjpegWriter::new(Vec::new(), turbojpeg::Compressor::new()?);
+/// writer.compressor().set_quality(80);
+/// for _ in 0..10 {
+///     let frame = turbojpeg::Image::mandelbrot(320, 240, turbojpeg::PixelFormat::RGB);
+///     writer.write_frame(frame.as_deref())?;
+/// }
+/// assert_eq!(writer.frame_count(), 10);
+///
+/// let stream = writer.into_inner()?;
+/// // every frame starts with the SOI marker
+/// assert_eq!(stream.windows(3).filter(|w| *w == [0xff, 0xd8, 0xff]).count(), 10);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct MjpegWriter<W: Write> {
+    writer: W,
+    compressor: Compressor,
+    buf: OutputBuf<'static>,
+    frame_count: usize,
+}
+
+impl<W: Write> MjpegWriter<W> {
+    /// Create a writer that compresses the frames using `compressor` and writes them to `writer`.
+    pub fn new(writer: W, compressor: Compressor) -> MjpegWriter<W> {
+        MjpegWriter { writer, compressor, buf: OutputBuf::new_owned(), frame_count: 0 }
+    }
+
+    /// Returns the compressor, so that you can change the compression parameters.
+    pub fn compressor(&mut self) -> &mut Compressor {
+        &mut self.compressor
+    }
+
+    /// Compress the `frame` and write it to the stream.
+    pub fn write_frame(&mut self, frame: Image<&[u8]>) -> Result<()> {
+        self.compressor.compress(frame, &mut self.buf)?;
+        self.writer.write_all(&self.buf)?;
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    /// Returns the number of frames written so far.
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    /// Returns a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Flush the underlying writer and return it.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
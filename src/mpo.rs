@@ -0,0 +1,194 @@
+use std::convert::TryInto as _;
+use crate::{Compressor, Image, marker};
+use crate::common::{Error, Result};
+
+/// Type of a multi-picture (MPO) file, as defined by the CIPA DC-007 standard.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u32)]
+pub enum MpoKind {
+    /// Images taken from slightly different viewpoints, for stereoscopic (3D) viewing.
+    Disparity = 0x020002,
+    /// Images of the same subject taken from different angles.
+    MultiAngle = 0x020003,
+    /// Images that form a panorama when put side by side.
+    Panorama = 0x020001,
+    /// Images that are related in some other way (such as a burst of photos).
+    Undefined = 0x000000,
+}
+
+/// Writer of multi-picture (MPO) files.
+///
+/// An MPO file is a sequence of JPEG images, where the first image contains an APP2 marker with
+/// the MP index (in the Multi-Picture Format, MPF) that lists the offsets and sizes of all images,
+/// and every other image contains an APP2 marker with its MP attributes (the MPF version and, for
+/// [`Disparity`][MpoKind::Disparity] and [`MultiAngle`][MpoKind::MultiAngle] files, the number of
+/// the image). Viewers that do not understand MPF display just the first image. The writer
+/// compresses the images using a [`Compressor`] and builds the index once all images are known.
+///
+/// # Example
+///
+/// ```
+/// // compress a stereo pair (here, two Mandelbrot sets)
+/// let left = turbojpeg::Image::mandelbrot(320, 240, turbojpeg::PixelFormat::RGB);
+/// let right = turbojpeg::Image::mandelbrot(320, 240, turbojpeg::PixelFormat::RGB);
+///
+/// let mut writer = turbojpeg::MpoWriter::new(
+///     turbojpeg::Compressor::new()?, turbojpeg::MpoKind::Disparity);
+/// writer.add_image(left.as_deref())?;
+/// writer.add_image(right.as_deref())?;
+/// let mpo_data = writer.finish()?;
+///
+/// // the first image is an ordinary JPEG
+/// let header = turbojpeg::read_header(&mpo_data)?;
+/// assert_eq!((header.width, header.height), (320, 240));
+///
+/// // both images contain an MPF marker
+/// assert_eq!(mpo_data.windows(4).filter(|w| w == b"MPF\0").count(), 2);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct MpoWriter {
+    compressor: Compressor,
+    kind: MpoKind,
+    images: Vec<Vec<u8>>,
+}
+
+/// Size of the TIFF header and the MP index IFD with three entries, before the MP entries.
+const INDEX_LEN: usize = 8 + 2 + 3*12 + 4;
+/// Size of one MP entry.
+const ENTRY_LEN: usize = 16;
+
+impl MpoWriter {
+    /// Create a writer that compresses the images using `compressor`.
+    pub fn new(compressor: Compressor, kind: MpoKind) -> MpoWriter {
+        MpoWriter { compressor, kind, images: Vec::new() }
+    }
+
+    /// Returns the compressor, so that you can change the compression parameters between images.
+    pub fn compressor(&mut self) -> &mut Compressor {
+        &mut self.compressor
+    }
+
+    /// Compress the `image` and add it to the file.
+    pub fn add_image(&mut self, image: Image<&[u8]>) -> Result<()> {
+        let jpeg_data = self.compressor.compress_to_vec(image)?;
+        self.images.push(jpeg_data);
+        Ok(())
+    }
+
+    /// Add an image that is already compressed to the file.
+    pub fn add_jpeg(&mut self, jpeg_data: Vec<u8>) {
+        self.images.push(jpeg_data);
+    }
+
+    /// Write the MP index into the first image and the MP attributes into the other images, and
+    /// concatenate all images into an MPO file.
+    ///
+    /// Panics if no image was added.
+    pub fn finish(self) -> Result<Vec<u8>> {
+        let MpoWriter { kind, mut images, .. } = self;
+        assert!(!images.is_empty(), "MpoWriter::finish() needs at least one image");
+
+        let to_u32 = |value: usize| -> Result<u32> {
+            value.try_into().map_err(|_| Error::IntegerOverflow("MPO size"))
+        };
+
+        // the images after the first one carry their MP attributes, which changes their sizes
+        for (i, image) in images.iter_mut().enumerate().skip(1) {
+            let offset = marker::app_insert_offset_after_exif(image);
+            image.splice(offset..offset, attribute_segment(kind, to_u32(i + 1)?));
+        }
+
+        let num_images = images.len();
+        let tiff_len = INDEX_LEN + ENTRY_LEN * num_images;
+        let segment_len = 2 + MPF_SIGNATURE.len() + tiff_len;
+        let segment_len_u16: u16 = segment_len.try_into()
+            .map_err(|_| Error::MarkerTooLarge(segment_len - 2))?;
+        let marker_len = 2 + segment_len;
+
+        // the offsets in the index are relative to the TIFF header in the first image
+        let insert_offset = marker::app_insert_offset_after_exif(&images[0]);
+        let tiff_offset = insert_offset + 4 + MPF_SIGNATURE.len();
+        let sizes = images.iter().enumerate()
+            .map(|(i, image)| image.len() + if i == 0 { marker_len } else { 0 })
+            .collect::<Vec<_>>();
+
+        let mut segment = Vec::with_capacity(marker_len);
+        segment.extend_from_slice(&[0xff, marker::APP2]);
+        segment.extend_from_slice(&segment_len_u16.to_be_bytes());
+        segment.extend_from_slice(MPF_SIGNATURE);
+
+        // TIFF header (big endian) pointing to the MP index IFD
+        segment.extend_from_slice(b"MM\0\x2a");
+        segment.extend_from_slice(&8u32.to_be_bytes());
+
+        // MP index IFD: MPFVersion, NumberOfImages and MPEntry
+        segment.extend_from_slice(&3u16.to_be_bytes());
+        write_ifd_entry(&mut segment, 0xb000, 7, 4, u32::from_be_bytes(*b"0100"));
+        write_ifd_entry(&mut segment, 0xb001, 4, 1, to_u32(num_images)?);
+        write_ifd_entry(&mut segment, 0xb002, 7, to_u32(ENTRY_LEN * num_images)?, to_u32(INDEX_LEN)?);
+        segment.extend_from_slice(&0u32.to_be_bytes());
+
+        // MP entries: attributes, size and offset of every image
+        let mut image_offset = 0;
+        for (i, &size) in sizes.iter().enumerate() {
+            // the first image is the representative image
+            let attributes = kind as u32 | if i == 0 { 0x2000_0000 } else { 0 };
+            let offset = if i == 0 { 0 } else { to_u32(image_offset - tiff_offset)? };
+            segment.extend_from_slice(&attributes.to_be_bytes());
+            segment.extend_from_slice(&to_u32(size)?.to_be_bytes());
+            segment.extend_from_slice(&offset.to_be_bytes());
+            segment.extend_from_slice(&[0; 4]); // no dependent images
+            image_offset += size;
+        }
+        debug_assert_eq!(segment.len(), marker_len);
+
+        let mut output = Vec::with_capacity(sizes.iter().sum());
+        let first = images.remove(0);
+        output.extend_from_slice(&first[..insert_offset]);
+        output.extend_from_slice(&segment);
+        output.extend_from_slice(&first[insert_offset..]);
+        for image in images {
+            output.extend_from_slice(&image);
+        }
+        Ok(output)
+    }
+}
+
+/// Signature at the start of an APP2 segment with MPF data.
+const MPF_SIGNATURE: &[u8] = b"MPF\0";
+
+/// Builds the APP2 segment with the MP attribute IFD of the image with the given (1-based)
+/// `number`.
+fn attribute_segment(kind: MpoKind, number: u32) -> Vec<u8> {
+    // the individual image number is required only in multi-view files
+    let multi_view = matches!(kind, MpoKind::Disparity | MpoKind::MultiAngle);
+    let num_entries: u16 = if multi_view { 2 } else { 1 };
+    let tiff_len = 8 + 2 + 12 * num_entries as usize + 4;
+    let segment_len = 2 + MPF_SIGNATURE.len() + tiff_len;
+
+    let mut segment = Vec::with_capacity(2 + segment_len);
+    segment.extend_from_slice(&[0xff, marker::APP2]);
+    segment.extend_from_slice(&(segment_len as u16).to_be_bytes());
+    segment.extend_from_slice(MPF_SIGNATURE);
+
+    // TIFF header (big endian) pointing to the MP attribute IFD: MPFVersion and MPIndividualNum
+    segment.extend_from_slice(b"MM\0\x2a");
+    segment.extend_from_slice(&8u32.to_be_bytes());
+    segment.extend_from_slice(&num_entries.to_be_bytes());
+    write_ifd_entry(&mut segment, 0xb000, 7, 4, u32::from_be_bytes(*b"0100"));
+    if multi_view {
+        write_ifd_entry(&mut segment, 0xb101, 4, 1, number);
+    }
+    segment.extend_from_slice(&0u32.to_be_bytes());
+    debug_assert_eq!(segment.len(), 2 + segment_len);
+    segment
+}
+
+fn write_ifd_entry(out: &mut Vec<u8>, tag: u16, typ: u16, count: u32, value: u32) {
+    out.extend_from_slice(&tag.to_be_bytes());
+    out.extend_from_slice(&typ.to_be_bytes());
+    out.extend_from_slice(&count.to_be_bytes());
+    out.extend_from_slice(&value.to_be_bytes());
+}
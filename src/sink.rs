@@ -0,0 +1,83 @@
+use crate::OwnedBuf;
+
+/// Destination for decompressed pixels.
+///
+/// This trait lets [`Decompressor::decompress_to_slice()`][crate::Decompressor::decompress_to_slice]
+/// write the pixels directly into memory that is not owned by this crate, such as a mapped staging
+/// buffer of a GPU library, which avoids an extra copy. It is implemented for `[u8]`, `Vec<u8>`
+/// and [`OwnedBuf`].
+///
+/// # Example
+///
+/// ```
+/// // a sink that pads the rows to a multiple of 256 bytes, as required for copying buffers into
+/// // textures by some GPU APIs
+/// struct Staging<'a> {
+///     mapped: &'a mut [u8],
+/// }
+///
+/// impl turbojpeg::PixelSink for Staging<'_> {
+///     fn pitch(&self, row_len: usize) -> usize {
+///         (row_len + 255) / 256 * 256
+///     }
+///
+///     fn pixel_buf(&mut self, len: usize) -> &mut [u8] {
+///         &mut self.mapped[..len]
+///     }
+/// }
+///
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let mut mapped = vec![0; 2 << 20];
+/// let mut staging = Staging { mapped: &mut mapped };
+///
+/// let mut decompressor = turbojpeg::Decompressor::new()?;
+/// let header = decompressor.decompress_to_slice(
+///     &jpeg_data, turbojpeg::PixelFormat::RGBA, &mut staging)?;
+/// assert_eq!((header.width, header.height), (384, 256));
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait PixelSink {
+    /// Returns the pitch (size of one image row in bytes) for an image whose rows have `row_len`
+    /// bytes of pixel data.
+    ///
+    /// The returned pitch must be at least `row_len`. The default implementation returns
+    /// `row_len`, so that the rows are tightly packed.
+    fn pitch(&self, row_len: usize) -> usize {
+        row_len
+    }
+
+    /// Returns the buffer where the pixels will be written.
+    ///
+    /// The buffer must have at least `len` bytes, where `len` is the pitch multiplied by the
+    /// height of the image. The buffer does not need to be zeroed, but it must be initialized.
+    fn pixel_buf(&mut self, len: usize) -> &mut [u8];
+}
+
+impl PixelSink for [u8] {
+    fn pixel_buf(&mut self, _len: usize) -> &mut [u8] {
+        self
+    }
+}
+
+impl PixelSink for Vec<u8> {
+    /// Resizes the vector to `len` bytes.
+    fn pixel_buf(&mut self, len: usize) -> &mut [u8] {
+        self.resize(len, 0);
+        &mut self[..]
+    }
+}
+
+impl PixelSink for OwnedBuf {
+    /// Replaces the buffer with a zeroed buffer of `len` bytes, unless it already has this length.
+    ///
+    /// Panics if `len` is larger than `i32::MAX`, which is the largest buffer that TurboJPEG can
+    /// allocate. [`Decompressor::decompress_to_owned()`][crate::Decompressor::decompress_to_owned]
+    /// returns an error instead.
+    fn pixel_buf(&mut self, len: usize) -> &mut [u8] {
+        if self.len() != len {
+            *self = OwnedBuf::zeroed(len);
+        }
+        &mut self[..]
+    }
+}
@@ -0,0 +1,109 @@
+use std::cell::RefCell;
+use crate::buf::OwnedBuf;
+use crate::compress::Compressor;
+use crate::common::{Result, Subsamp};
+use crate::decompress::Decompressor;
+use crate::image_rs::{JpegPixel, compress_image_with, decompress_image_with};
+
+thread_local! {
+    // compressors and decompressors are cached in the threads of the blocking pool, which tokio
+    // reuses between tasks, so that we don't need to create a new TurboJPEG instance every time
+    static COMPRESSOR: RefCell<Option<Compressor>> = const { RefCell::new(None) };
+    static DECOMPRESSOR: RefCell<Option<Decompressor>> = const { RefCell::new(None) };
+}
+
+/// Compresses an [`image::ImageBuffer`] into JPEG without blocking the async runtime.
+///
+/// This is the same as [`compress_image()`][crate::compress_image], but the compression runs on
+/// the blocking thread pool of tokio using [`tokio::task::spawn_blocking()`], so it does not stall
+/// the other tasks of the runtime. The compressors are cached in the threads of the pool and
+/// reused for later calls. The image is moved into the blocking task, so this function takes it
+/// by value.
+///
+/// This function must be called from the context of a tokio runtime. If the compression panics,
+/// the panic is propagated to the caller.
+///
+/// # Example
+///
+/// ```
+/// let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+/// let jpeg_data = runtime.block_on(async {
+///     // create an `image::RgbImage`
+///     let image = image::RgbImage::from_fn(256, 256, |x, y| image::Rgb([x as u8, y as u8, 128]));
+///
+///     // compress `image` into JPEG on a blocking thread
+///     turbojpeg::compress_image_async(image, 95, turbojpeg::Subsamp::Sub2x2).await
+/// })?;
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg_attr(docsrs, doc(cfg(all(feature = "image", feature = "tokio"))))]
+pub async fn compress_image_async<P>(
+    image_buf: image::ImageBuffer<P, Vec<u8>>,
+    quality: i32,
+    subsamp: Subsamp,
+) -> Result<OwnedBuf>
+    where P: JpegPixel + Send + 'static
+{
+    spawn_blocking(move || COMPRESSOR.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let compressor = match cell.as_mut() {
+            Some(compressor) => compressor,
+            None => cell.insert(Compressor::new()?),
+        };
+        compressor.reset();
+        compressor.set_quality(quality);
+        compressor.set_subsamp(subsamp);
+        compress_image_with(compressor, &image_buf)
+    })).await
+}
+
+/// Decompresses image from JPEG into an [`image::ImageBuffer`] without blocking the async
+/// runtime.
+///
+/// This is the same as [`decompress_image()`][crate::decompress_image], but the decompression runs
+/// on the blocking thread pool of tokio using [`tokio::task::spawn_blocking()`], so it does not
+/// stall the other tasks of the runtime. The decompressors are cached in the threads of the pool
+/// and reused for later calls. The JPEG data is moved into the blocking task, so you can pass any
+/// owned buffer, such as `Vec<u8>` or [`OwnedBuf`].
+///
+/// This function must be called from the context of a tokio runtime. If the decompression panics,
+/// the panic is propagated to the caller.
+///
+/// # Example
+///
+/// ```
+/// let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+/// let image: image::RgbImage = runtime.block_on(async {
+///     // read JPEG data from file
+///     let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+///
+///     // decompress `jpeg_data` on a blocking thread
+///     turbojpeg::decompress_image_async(jpeg_data).await
+/// })?;
+/// assert_eq!(image.dimensions(), (384, 256));
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg_attr(docsrs, doc(cfg(all(feature = "image", feature = "tokio"))))]
+pub async fn decompress_image_async<P, T>(jpeg_data: T) -> Result<image::ImageBuffer<P, Vec<u8>>>
+    where P: JpegPixel + Send + 'static, T: AsRef<[u8]> + Send + 'static
+{
+    spawn_blocking(move || DECOMPRESSOR.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let decompressor = match cell.as_mut() {
+            Some(decompressor) => decompressor,
+            None => cell.insert(Decompressor::new()?),
+        };
+        decompress_image_with(decompressor, jpeg_data.as_ref())
+    })).await
+}
+
+async fn spawn_blocking<F, R>(f: F) -> R
+    where F: FnOnce() -> R + Send + 'static, R: Send + 'static
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
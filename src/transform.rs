@@ -1,7 +1,8 @@
 use std::ptr;
 use std::convert::TryInto as _;
 use crate::buf::{OwnedBuf, OutputBuf};
-use crate::common::{Error, Result, get_error};
+use crate::common::{Error, Result, Subsamp, get_error};
+use crate::metrics::{self, Operation};
 
 /// Transforms JPEG images without recompression.
 ///
@@ -101,6 +102,36 @@ pub struct Transform {
     pub copy_none: bool,
 }
 
+impl Transform {
+    /// Transform that removes the metadata from the image without changing it otherwise.
+    ///
+    /// This is the same as a [`TransformOp::None`] transform with [`copy_none`][Self::copy_none]
+    /// set: the DCT coefficients are copied unchanged, so the image decompresses to exactly the
+    /// same pixels, but the EXIF, XMP, ICC profile, comments and all other APPn markers of the
+    /// input image are dropped. TurboJPEG still writes a JFIF APP0 marker into the output.
+    ///
+    /// Like all transforms, the output uses baseline entropy coding unless you also set
+    /// [`progressive`][Self::progressive] (for example, when the
+    /// [`entropy_coding`][crate::DecompressHeader::entropy_coding] of the input is progressive).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// // strip the EXIF and XMP metadata
+    /// let stripped_data = turbojpeg::transform(&turbojpeg::Transform::strip_metadata(), &jpeg_data)?;
+    /// assert!(stripped_data.len() < jpeg_data.len());
+    /// assert!(!stripped_data.windows(6).any(|w| w == b"Exif\0\0"));
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn strip_metadata() -> Transform {
+        Transform { op: TransformOp::None, copy_none: true, .. Transform::default() }
+    }
+}
+
 /// Transform operation.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[doc(alias = "TJXOP")]
@@ -187,6 +218,58 @@ pub struct TransformCrop {
     pub height: Option<usize>,
 }
 
+impl TransformOp {
+    /// Returns the operation that rotates the image clockwise by `degrees`.
+    ///
+    /// `degrees` must be a multiple of 90, and it may be negative (counter-clockwise) or larger
+    /// than 360, so -90 is the same as 270, for example. A rotation by a multiple of 360 returns
+    /// [`TransformOp::None`]. Other angles return [`Error::BadRotation`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use turbojpeg::TransformOp;
+    /// assert_eq!(TransformOp::rotate(90)?, TransformOp::Rot90);
+    /// assert_eq!(TransformOp::rotate(-90)?, TransformOp::Rot270);
+    /// assert_eq!(TransformOp::rotate(540)?, TransformOp::Rot180);
+    /// assert_eq!(TransformOp::rotate(0)?, TransformOp::None);
+    /// assert!(TransformOp::rotate(45).is_err());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn rotate(degrees: i32) -> Result<TransformOp> {
+        match degrees.rem_euclid(360) {
+            0 => Ok(TransformOp::None),
+            90 => Ok(TransformOp::Rot90),
+            180 => Ok(TransformOp::Rot180),
+            270 => Ok(TransformOp::Rot270),
+            _ => Err(Error::BadRotation(degrees)),
+        }
+    }
+
+    /// Returns true if the operation swaps the width and height of the image.
+    fn is_transposing(self) -> bool {
+        matches!(self, TransformOp::Transpose | TransformOp::Transverse
+            | TransformOp::Rot90 | TransformOp::Rot270)
+    }
+}
+
+/// Cropping region that was actually used by
+/// [`Transformer::transform_snapped()`][Transformer::transform_snapped].
+///
+/// The coordinates are in pixels of the transformed image, like the coordinates in
+/// [`TransformCrop`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ActualCrop {
+    /// Left boundary of the region.
+    pub x: usize,
+    /// Upper boundary of the region.
+    pub y: usize,
+    /// Width of the region.
+    pub width: usize,
+    /// Height of the region.
+    pub height: usize,
+}
+
 impl Transformer {
     /// Create a new transformer instance.
     #[doc(alias = "tjInitTransform")]
@@ -240,6 +323,21 @@ impl Transformer {
         jpeg_data: &[u8],
         output: &mut OutputBuf,
     ) -> Result<()> {
+        let start = metrics::start();
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!("transform",
+            op = ?transform.op, crop = ?transform.crop, input_len = jpeg_data.len(),
+            output_len = tracing::field::Empty,
+        ).entered();
+        if !output.is_owned {
+            // with TJFLAG_NOREALLOC, TurboJPEG ignores the size of the buffer that we pass and
+            // assumes that it is large enough for the worst case, so we must check that first
+            let required_len = self.required_len(transform, jpeg_data)?;
+            if output.cap < required_len {
+                return Err(Error::OutputTooSmall(required_len))
+            }
+        }
+
         let mut options = 0;
         if transform.perfect { options |= raw::TJXOPT_PERFECT }
         if transform.trim { options |= raw::TJXOPT_TRIM }
@@ -271,7 +369,8 @@ impl Transformer {
             customFilter: None,
         };
 
-        let mut output_len = output.len as libc::c_ulong;
+        let ptr_before = output.ptr;
+        let mut output_len = output.cap as libc::c_ulong;
         let res = unsafe {
             raw::tjTransform(
                 self.handle,
@@ -283,6 +382,7 @@ impl Transformer {
         };
 
         output.len = output_len as usize;
+        output.update_cap(ptr_before);
 
         if res != 0 {
             Err(unsafe { get_error(self.handle) })
@@ -290,10 +390,244 @@ impl Transformer {
             output.len = 0;
             Err(Error::Null())
         } else {
+            output.apply_growth_policy();
+            metrics::record(start, Operation::Transform, jpeg_data.len(), output.len, 0);
+            #[cfg(feature = "tracing")]
+            span.record("output_len", output.len);
             Ok(())
         }
     }
 
+    /// Returns the size of the buffer that TurboJPEG assumes when it cannot reallocate the output
+    /// of `transform`.
+    ///
+    /// TurboJPEG computes this with `tjBufSize()` from the size of the (cropped) image; we use an
+    /// upper bound of that, because the crop region is expanded to the MCU boundaries and the
+    /// image may be transposed.
+    fn required_len(&mut self, transform: &Transform, jpeg_data: &[u8]) -> Result<usize> {
+        let jpeg_data_len = jpeg_data.len().try_into()
+            .map_err(|_| Error::IntegerOverflow("jpeg_data.len()"))?;
+        let mut width = 0;
+        let mut height = 0;
+        let mut subsamp = 0;
+        let mut colorspace = 0;
+        let res = unsafe {
+            raw::tjDecompressHeader3(
+                self.handle,
+                jpeg_data.as_ptr(), jpeg_data_len,
+                &mut width, &mut height, &mut subsamp, &mut colorspace,
+            )
+        };
+        if res != 0 {
+            return Err(unsafe { get_error(self.handle) })
+        }
+
+        let mut width: usize = width.try_into().map_err(|_| Error::IntegerOverflow("width"))?;
+        let mut height: usize = height.try_into().map_err(|_| Error::IntegerOverflow("height"))?;
+        let subsamp = Subsamp::from_u32(subsamp as u32)?;
+        if let Some(crop) = transform.crop {
+            // the crop region may be given in the transposed coordinates and its origin is moved
+            // back by less than one MCU
+            let max_dim = width.max(height);
+            let mcu_dim = subsamp.mcu_width().max(subsamp.mcu_height());
+            let crop_dim = |dim: Option<usize>| {
+                dim.map_or(max_dim, |dim| dim.saturating_add(mcu_dim).min(max_dim))
+            };
+            (width, height) = (crop_dim(crop.width), crop_dim(crop.height));
+        }
+        Ok(usize::max(
+            crate::compressed_buf_len(width, height, subsamp)?,
+            crate::compressed_buf_len(height, width, subsamp)?,
+        ))
+    }
+
+    /// Returns true if all DCT coefficients of the chrominance components (the components other
+    /// than the first) are zero, up to the given `tolerance`.
+    ///
+    /// This reads the coefficients using a custom filter in a transform that does not produce
+    /// any output, so the entropy-coded data is decoded, but no inverse DCT, upsampling or color
+    /// conversion is performed. libjpeg decodes the coefficients of the whole image before it calls
+    /// the filter, so the cost is dominated by the entropy decoding; the filter stops the
+    /// transform at the first block that exceeds the `tolerance`.
+    pub(crate) fn chroma_is_flat(&mut self, jpeg_data: &[u8], tolerance: i16) -> Result<bool> {
+        struct State {
+            tolerance: i16,
+            flat: bool,
+        }
+
+        unsafe extern "C" fn filter(
+            coeffs: *mut libc::c_short,
+            array_region: raw::tjregion,
+            _plane_region: raw::tjregion,
+            component_index: libc::c_int,
+            _transform_index: libc::c_int,
+            transform: *mut raw::tjtransform,
+        ) -> libc::c_int {
+            if component_index == 0 {
+                return 0
+            }
+            let state = &mut *((*transform).data as *mut State);
+            let len = array_region.w as usize * array_region.h as usize;
+            let coeffs = std::slice::from_raw_parts(coeffs, len);
+            if coeffs.iter().any(|&c| c.unsigned_abs() > state.tolerance as u16) {
+                // stop the transform as soon as we find a colored block
+                state.flat = false;
+                return -1
+            }
+            0
+        }
+
+        let mut state = State { tolerance, flat: true };
+        let mut transform = raw::tjtransform {
+            r: raw::tjregion { x: 0, y: 0, w: 0, h: 0 },
+            op: raw::TJXOP_TJXOP_NONE as libc::c_int,
+            options: raw::TJXOPT_NOOUTPUT as libc::c_int,
+            data: &mut state as *mut State as *mut libc::c_void,
+            customFilter: Some(filter),
+        };
+
+        let mut output_ptr = ptr::null_mut();
+        let mut output_len = 0;
+        let res = unsafe {
+            raw::tjTransform(
+                self.handle,
+                jpeg_data.as_ptr(), jpeg_data.len() as libc::c_ulong,
+                1, &mut output_ptr, &mut output_len,
+                &mut transform,
+                0,
+            )
+        };
+        unsafe { raw::tjFree(output_ptr) };
+
+        // the filter makes the transform fail when it finds a colored block
+        if res != 0 && state.flat {
+            return Err(unsafe { get_error(self.handle) })
+        }
+        Ok(state.flat)
+    }
+
+    /// Losslessly crops `jpeg_data` into horizontal bands that span the whole width of the image.
+    ///
+    /// Every band is given by its first row, which must be aligned on an MCU boundary, and its
+    /// height. All bands are cropped in a single call to `tjTransform()`, so the entropy-coded data
+    /// of the image is decoded only once. The bands do not contain any extra markers.
+    pub(crate) fn crop_bands(
+        &mut self,
+        jpeg_data: &[u8],
+        bands: &[(usize, usize)],
+    ) -> Result<Vec<OwnedBuf>> {
+        let mut transforms = bands.iter()
+            .map(|&(y, height)| Ok(raw::tjtransform {
+                r: raw::tjregion {
+                    x: 0,
+                    y: y.try_into().map_err(|_| Error::IntegerOverflow("band y"))?,
+                    w: 0,
+                    h: height.try_into().map_err(|_| Error::IntegerOverflow("band height"))?,
+                },
+                op: raw::TJXOP_TJXOP_NONE as libc::c_int,
+                options: (raw::TJXOPT_CROP | raw::TJXOPT_COPYNONE) as libc::c_int,
+                data: ptr::null_mut(),
+                customFilter: None,
+            }))
+            .collect::<Result<Vec<_>>>()?;
+        let num_bands = bands.len().try_into().map_err(|_| Error::IntegerOverflow("bands.len()"))?;
+
+        let mut output_ptrs = vec![ptr::null_mut(); bands.len()];
+        let mut output_lens = vec![0; bands.len()];
+        let res = unsafe {
+            raw::tjTransform(
+                self.handle,
+                jpeg_data.as_ptr(), jpeg_data.len() as libc::c_ulong,
+                num_bands, output_ptrs.as_mut_ptr(), output_lens.as_mut_ptr(),
+                transforms.as_mut_ptr(),
+                0,
+            )
+        };
+
+        // take ownership of the buffers allocated by TurboJPEG, so that they are freed even if the
+        // transform failed
+        let outputs = output_ptrs.into_iter().zip(output_lens)
+            .map(|(ptr, len)| unsafe {
+                OwnedBuf::from_raw(ptr, if ptr.is_null() { 0 } else { len as usize })
+            })
+            .collect::<Vec<_>>();
+        if res != 0 {
+            return Err(unsafe { get_error(self.handle) })
+        }
+        Ok(outputs)
+    }
+
+    /// Apply a transformation to the compressed JPEG, snapping the crop region to MCU boundaries.
+    ///
+    /// This works like [`transform()`](Transformer::transform), but if the [crop
+    /// region][Transform::crop] is not aligned on MCU boundaries, its upper left corner is moved
+    /// up and left to the nearest boundary, and its size is extended so that the cropped image
+    /// still contains the whole requested region. The region that was actually used is returned
+    /// (if the transform does not crop, this is the whole image).
+    ///
+    /// Panics if the upper left corner of the crop region lies outside of the image.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// // crop a region that is not aligned on the 8x8 MCU blocks of this image
+    /// let transform = turbojpeg::Transform {
+    ///     crop: Some(turbojpeg::TransformCrop { x: 13, y: 21, width: Some(100), height: Some(50) }),
+    ///     .. turbojpeg::Transform::default()
+    /// };
+    ///
+    /// let mut transformer = turbojpeg::Transformer::new()?;
+    /// let mut cropped_data = turbojpeg::OutputBuf::new_owned();
+    /// let crop = transformer.transform_snapped(&transform, &jpeg_data, &mut cropped_data)?;
+    /// assert_eq!(crop, turbojpeg::ActualCrop { x: 8, y: 16, width: 105, height: 55 });
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn transform_snapped(
+        &mut self,
+        transform: &Transform,
+        jpeg_data: &[u8],
+        output: &mut OutputBuf,
+    ) -> Result<ActualCrop> {
+        let header = crate::parse_header(jpeg_data)?;
+        let (mut image_width, mut image_height) = (header.width, header.height);
+        let (mut mcu_width, mut mcu_height) = header.subsamp.mcu_size();
+        // the crop region is specified in the coordinates of the transformed image
+        if transform.op.is_transposing() {
+            (image_width, image_height) = (image_height, image_width);
+            (mcu_width, mcu_height) = (mcu_height, mcu_width);
+        }
+
+        let actual = match transform.crop {
+            Some(crop) => {
+                assert!(crop.x < image_width && crop.y < image_height,
+                    "crop origin ({}, {}) is outside of the image with size {}x{}",
+                    crop.x, crop.y, image_width, image_height);
+                let end_x = crop.width.map_or(image_width, |w| usize::min(crop.x + w, image_width));
+                let end_y = crop.height.map_or(image_height, |h| usize::min(crop.y + h, image_height));
+                let x = crop.x / mcu_width * mcu_width;
+                let y = crop.y / mcu_height * mcu_height;
+                ActualCrop { x, y, width: end_x - x, height: end_y - y }
+            },
+            None => ActualCrop { x: 0, y: 0, width: image_width, height: image_height },
+        };
+
+        let mut transform = transform.clone();
+        if transform.crop.is_some() {
+            transform.crop = Some(TransformCrop {
+                x: actual.x,
+                y: actual.y,
+                width: Some(actual.width),
+                height: Some(actual.height),
+            });
+        }
+        self.transform(&transform, jpeg_data, output)?;
+        Ok(actual)
+    }
+
     /// Transforms the `image` into an owned buffer.
     ///
     /// This method automatically allocates the memory and avoids needless copying.
@@ -316,13 +650,43 @@ impl Transformer {
 
     /// Transform the `image` into the slice `output`.
     ///
-    /// Returns the size of the transformed JPEG data. If the transformed image does not fit into
-    /// `dest`, this method returns an error.
+    /// Returns the size of the transformed JPEG data. The slice must be at least as large as
+    /// [`compressed_buf_len()`][crate::compressed_buf_len] for the transformed (and possibly
+    /// cropped) image, otherwise this method returns [`Error::OutputTooSmall`] without transforming
+    /// the image, because TurboJPEG cannot safely write into a smaller buffer. The required size is
+    /// computed from the header of `jpeg_data`, so it is slightly larger than
+    /// [`compressed_buf_len()`][crate::compressed_buf_len] for cropped or transposed images.
     ///
-    /// You can use [`compressed_buf_len()`][crate::compressed_buf_len] to determine buffer size that
-    /// should be enough for the image, but there are some rare cases (such as transforming images
-    /// with a large amount of embedded EXIF or ICC profile data) in which the output image will be
-    /// larger than the size returned by [`compressed_buf_len()`][crate::compressed_buf_len].
+    /// There are some rare cases (such as transforming images with a large amount of embedded EXIF
+    /// or ICC profile data) in which the output image will be larger even than that; in this case,
+    /// this method also returns an error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// let mut transformer = turbojpeg::Transformer::new()?;
+    /// let transform = turbojpeg::Transform {
+    ///     op: turbojpeg::TransformOp::Hflip,
+    ///     .. turbojpeg::Transform::default()
+    /// };
+    ///
+    /// // a slice that is too small for the 384x256 image is rejected
+    /// let mut output = vec![0; 1000];
+    /// let res = transformer.transform_to_slice(&transform, &jpeg_data, &mut output);
+    /// assert!(matches!(res, Err(turbojpeg::Error::OutputTooSmall(_))));
+    ///
+    /// // a slice of the worst-case size is large enough
+    /// let len = turbojpeg::compressed_buf_len(384, 256, turbojpeg::Subsamp::None)?;
+    /// let mut output = vec![0; len];
+    /// let flipped_len = transformer.transform_to_slice(&transform, &jpeg_data, &mut output)?;
+    /// assert_eq!(&output[..2], &[0xff, 0xd8]);
+    /// assert!(flipped_len <= len);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
     pub fn transform_to_slice(
         &mut self,
         transform: &Transform,
@@ -0,0 +1,128 @@
+use std::ops::Deref;
+use crate::common::Subsamp;
+
+/// An image in the YUV (YCbCr) colorspace with separate planes, with pixels of type `T`.
+///
+/// This is the format produced by
+/// [`Decompressor::decompress_to_yuv()`][crate::Decompressor::decompress_to_yuv]: the luminance
+/// (Y) plane is followed by the two chrominance (U and V, or Cb and Cr) planes, which are smaller
+/// than the image if it uses [chrominance subsampling][Subsamp]. Grayscale images consist only of
+/// the Y plane. Each row of each plane is padded to a multiple of `align` bytes. Use
+/// [`plane()`](YuvImage::plane) to access the planes, so that you don't need to compute their
+/// geometry yourself.
+#[derive(Debug, Copy, Clone)]
+pub struct YuvImage<T> {
+    /// Pixel data of all planes (typically `&[u8]` or `Vec<u8>`).
+    pub pixels: T,
+    /// Width of the image in pixels.
+    pub width: usize,
+    /// Number of bytes that the rows of every plane are padded to. This must be a power of two.
+    pub align: usize,
+    /// Height of the image in pixels.
+    pub height: usize,
+    /// Chrominance subsampling, which determines the size of the chrominance planes.
+    pub subsamp: Subsamp,
+}
+
+/// View of a single plane of a [`YuvImage`], returned by [`YuvImage::plane()`].
+///
+/// Sample in column `x` and row `y` of the plane is stored in `data` at offset `y*stride + x`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PlaneView<'a> {
+    /// Samples of the plane, including the padding at the end of each row.
+    pub data: &'a [u8],
+    /// Width of the plane in samples.
+    pub width: usize,
+    /// Height of the plane in rows.
+    pub height: usize,
+    /// Size of one row of the plane in bytes.
+    pub stride: usize,
+}
+
+impl<T> YuvImage<T> {
+    /// Returns the number of planes: 1 for grayscale images and 3 otherwise.
+    pub fn num_planes(&self) -> usize {
+        if self.subsamp == Subsamp::Gray { 1 } else { 3 }
+    }
+
+    /// Returns the width, height and stride of the plane with index `idx`.
+    ///
+    /// The planes are computed in the same way as `tjPlaneWidth()` and `tjPlaneHeight()` in
+    /// TurboJPEG: the luminance plane is padded to a whole number of chrominance samples.
+    ///
+    /// Panics if `idx` is not a valid plane index.
+    #[doc(alias = "tjPlaneWidth")]
+    #[doc(alias = "tjPlaneHeight")]
+    pub fn plane_size(&self, idx: usize) -> (usize, usize, usize) {
+        assert!(idx < self.num_planes(),
+            "plane index {} is out of range for an image with {} planes", idx, self.num_planes());
+        assert!(self.align.is_power_of_two(), "align {} is not a power of two", self.align);
+        let (h_factor, v_factor) = (self.subsamp.mcu_width() / 8, self.subsamp.mcu_height() / 8);
+        let mut width = self.width.div_ceil(h_factor) * h_factor;
+        let mut height = self.height.div_ceil(v_factor) * v_factor;
+        if idx != 0 {
+            width /= h_factor;
+            height /= v_factor;
+        }
+        let stride = width.div_ceil(self.align) * self.align;
+        (width, height, stride)
+    }
+
+    /// Returns the number of bytes of pixel data needed for all planes.
+    #[doc(alias = "tjBufSizeYUV2")]
+    pub fn buf_len(&self) -> usize {
+        (0..self.num_planes())
+            .map(|idx| {
+                let (_, height, stride) = self.plane_size(idx);
+                height * stride
+            })
+            .sum()
+    }
+}
+
+impl<T: Deref<Target = [u8]>> YuvImage<T> {
+    /// Returns the plane with index `idx` (0 for Y, 1 for U and 2 for V).
+    ///
+    /// Panics if `idx` is not a valid plane index or if `pixels` is too short for the image.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file and decompress it to YUV
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let yuv = decompressor.decompress_to_yuv(&jpeg_data, 16)?;
+    ///
+    /// // the image uses 4:4:4 subsampling, so all planes have the same size
+    /// let y = yuv.plane(0);
+    /// let u = yuv.plane(1);
+    /// assert_eq!((y.width, y.height, y.stride), (384, 256, 384));
+    /// assert_eq!((u.width, u.height, u.stride), (384, 256, 384));
+    ///
+    /// // with 4:2:0 subsampling, the chrominance planes are smaller
+    /// let image = turbojpeg::Image::mandelbrot(100, 75, turbojpeg::PixelFormat::RGB);
+    /// let jpeg_data = turbojpeg::compress(image.as_deref(), 90, turbojpeg::Subsamp::Sub2x2)?;
+    /// let yuv = decompressor.decompress_to_yuv(&jpeg_data, 16)?;
+    /// let y = yuv.plane(0);
+    /// let v = yuv.plane(2);
+    /// assert_eq!((y.width, y.height, y.stride), (100, 76, 112));
+    /// assert_eq!((v.width, v.height, v.stride), (50, 38, 64));
+    /// assert_eq!(v.data.len(), 38 * 64);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn plane(&self, idx: usize) -> PlaneView<'_> {
+        let offset = (0..idx)
+            .map(|idx| {
+                let (_, height, stride) = self.plane_size(idx);
+                height * stride
+            })
+            .sum::<usize>();
+        let (width, height, stride) = self.plane_size(idx);
+        let len = height * stride;
+        assert!(offset + len <= self.pixels.len(),
+            "pixels length {} is too small for width {}, height {}, align {} and subsampling {:?}",
+            self.pixels.len(), self.width, self.height, self.align, self.subsamp);
+        PlaneView { data: &self.pixels[offset..][..len], width, height, stride }
+    }
+}